@@ -0,0 +1,276 @@
+use std::{collections::HashMap, env, net::IpAddr};
+
+use actix_web::{
+    HttpMessage, body::MessageBody, dev::{Payload, ServiceRequest, ServiceResponse},
+    error::{ErrorForbidden, ErrorInternalServerError, ErrorUnauthorized}, http::{Method, header::UPGRADE}, middleware::Next, web
+};
+use aes_gcm::{Aes256Gcm, Nonce, aead::{Aead, KeyInit as AeadKeyInit}};
+use chrono::Utc;
+use hmac::{Hmac, KeyInit, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::{AppData, auth::AuthUser, db::get_active_api_key};
+
+pub const API_KEY_HEADER: &str = "X-API-KEY";
+pub const API_TIMESTAMP_HEADER: &str = "X-API-TIMESTAMP";
+pub const API_SIGNATURE_HEADER: &str = "X-API-SIGNATURE";
+
+//requests signed further than this from the server clock are rejected
+const RECV_WINDOW_MS: i64 = 5000;
+
+//AES-GCM nonce stored in front of each encrypted secret
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiKeyScope {
+    Read,
+    Trade,
+    Withdraw,
+}
+
+impl ApiKeyScope {
+    pub fn all() -> Vec<ApiKeyScope> {
+        vec![ApiKeyScope::Read, ApiKeyScope::Trade, ApiKeyScope::Withdraw]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiKeyScope::Read => "read",
+            ApiKeyScope::Trade => "trade",
+            ApiKeyScope::Withdraw => "withdraw"
+        }
+    }
+
+    pub fn parse(scope: &str) -> Option<ApiKeyScope> {
+        match scope {
+            "read" => Some(ApiKeyScope::Read),
+            "trade" => Some(ApiKeyScope::Trade),
+            "withdraw" => Some(ApiKeyScope::Withdraw),
+            _ => None
+        }
+    }
+}
+
+//signatures seen within the replay window, each one is accepted once
+pub struct ReplayCache {
+    seen: HashMap<String, i64>
+}
+
+impl ReplayCache {
+    pub fn default() -> Self {
+        Self {
+            seen: HashMap::new()
+        }
+    }
+
+    //false if the signature was already used
+    pub fn insert(&mut self, signature: &str, timestamp: i64, now: i64) -> bool {
+        self.seen.retain(|_, seen_at| *seen_at >= now - RECV_WINDOW_MS);
+
+        if self.seen.contains_key(signature) {
+            return false;
+        }
+
+        self.seen.insert(signature.to_string(), timestamp);
+        true
+    }
+}
+
+//returns (api_key, secret). the secret is shown to the user once and stored encrypted
+pub fn generate_api_key() -> (String, String) {
+    let api_key = hex::encode(rand::random::<[u8; 16]>());
+    let secret = hex::encode(rand::random::<[u8; 32]>());
+    (api_key, secret)
+}
+
+//secrets are encrypted with AES-256-GCM under API_KEY_ENCRYPTION_KEY, 64 hex characters from the environment
+fn encryption_key() -> anyhow::Result<Aes256Gcm> {
+    let key = match env::var("API_KEY_ENCRYPTION_KEY") {
+        Ok(key) if !key.is_empty() => hex::decode(key)?,
+        _ => return Err(anyhow::anyhow!("API_KEY_ENCRYPTION_KEY is not set"))
+    };
+
+    Aes256Gcm::new_from_slice(&key).map_err(|_| anyhow::anyhow!("API_KEY_ENCRYPTION_KEY must be 32 bytes"))
+}
+
+//hex of the nonce followed by the ciphertext
+pub fn encrypt_secret(secret: &str) -> anyhow::Result<String> {
+    let nonce = rand::random::<[u8; NONCE_LEN]>();
+    let ciphertext = encryption_key()?
+        .encrypt(Nonce::from_slice(&nonce), secret.as_bytes())
+        .map_err(|_| anyhow::anyhow!("Failed to encrypt API secret"))?;
+
+    Ok(hex::encode([nonce.as_slice(), &ciphertext].concat()))
+}
+
+fn decrypt_secret(encrypted_secret: &str) -> anyhow::Result<Vec<u8>> {
+    let encrypted_secret = hex::decode(encrypted_secret)?;
+    if encrypted_secret.len() < NONCE_LEN {
+        return Err(anyhow::anyhow!("Encrypted API secret is too short"));
+    }
+
+    let (nonce, ciphertext) = encrypted_secret.split_at(NONCE_LEN);
+    encryption_key()?
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| anyhow::anyhow!("Failed to decrypt API secret"))
+}
+
+//message signed by the client: timestamp + method + path with query + raw body,
+//HMAC-SHA256 keyed with the secret as given to the client. the signature is sent hex encoded
+pub fn signing_payload(timestamp: &str, method: &str, path: &str, body: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(timestamp.len() + method.len() + path.len() + body.len());
+    payload.extend_from_slice(timestamp.as_bytes());
+    payload.extend_from_slice(method.as_bytes());
+    payload.extend_from_slice(path.as_bytes());
+    payload.extend_from_slice(body);
+    payload
+}
+
+fn verify_signature(secret: &[u8], payload: &[u8], signature: &str) -> bool {
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret) else {
+        return false;
+    };
+    mac.update(payload);
+    mac.verify_slice(&signature).is_ok()
+}
+
+fn header<'a>(req: &'a ServiceRequest, name: &str) -> Option<&'a str> {
+    req.headers().get(name).and_then(|value| value.to_str().ok())
+}
+
+//requests carrying X-API-KEY must be signed. on success the key's user and scopes are put in the
+//request extensions for the AuthUser extractor, requests without the header pass through untouched
+pub async fn verify_api_key(mut req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    if header(&req, API_KEY_HEADER).is_none() {
+        return next.call(req).await;
+    }
+
+    let auth_user = authenticate_api_key(&mut req).await?;
+    req.extensions_mut().insert(auth_user);
+
+    next.call(req).await
+}
+
+async fn authenticate_api_key(req: &mut ServiceRequest) -> Result<AuthUser, actix_web::Error> {
+    let (Some(api_key), Some(timestamp), Some(signature)) = (
+        header(req, API_KEY_HEADER).map(str::to_string),
+        header(req, API_TIMESTAMP_HEADER).map(str::to_string),
+        header(req, API_SIGNATURE_HEADER).map(str::to_string)
+    ) else {
+        return Err(ErrorUnauthorized("Missing API key signature headers"));
+    };
+
+    //replay window
+    let now = Utc::now().timestamp_millis();
+    let Ok(timestamp_ms) = timestamp.parse::<i64>() else {
+        return Err(ErrorUnauthorized("Invalid request timestamp"));
+    };
+    if (now - timestamp_ms).abs() > RECV_WINDOW_MS {
+        return Err(ErrorUnauthorized("Request timestamp outside the receive window"));
+    }
+
+    let Some(data) = req.app_data::<web::Data<AppData>>().cloned() else {
+        return Err(ErrorInternalServerError("App data missing"));
+    };
+
+    let db_api_key = match get_active_api_key(&data.pool, &api_key).await {
+        Ok(Some(db_api_key)) => db_api_key,
+        Ok(None) => return Err(ErrorUnauthorized("Invalid API key")),
+        Err(e) => return Err(ErrorInternalServerError(e.to_string()))
+    };
+
+    //ip allowlist, checked against the socket address so it can not be spoofed with headers
+    if !db_api_key.allowed_ips.is_empty() {
+        let peer_ip = req.peer_addr().map(|addr| addr.ip());
+        let allowed = db_api_key.allowed_ips.iter()
+            .filter_map(|ip| ip.parse::<IpAddr>().ok())
+            .any(|ip| Some(ip) == peer_ip);
+
+        if !allowed {
+            return Err(ErrorForbidden("Address not allowed for this API key"));
+        }
+    }
+
+    //read the body for the signature and put it back for the handler. GET requests and websocket
+    //upgrades have no body to sign, and reading one would consume the upgraded stream
+    let body = if req.method() == Method::GET || req.method() == Method::HEAD || req.headers().contains_key(UPGRADE) {
+        web::Bytes::new()
+    } else {
+        let body = req.extract::<web::Bytes>().await?;
+        req.set_payload(Payload::from(body.clone()));
+        body
+    };
+
+    let path = req.uri().path_and_query().map(|path| path.as_str()).unwrap_or("/");
+    let payload = signing_payload(&timestamp, req.method().as_str(), path, &body);
+    let secret = match decrypt_secret(&db_api_key.encrypted_secret) {
+        Ok(secret) => secret,
+        Err(e) => return Err(ErrorInternalServerError(e.to_string()))
+    };
+    if !verify_signature(&secret, &payload, &signature) {
+        return Err(ErrorUnauthorized("Invalid signature"));
+    }
+
+    if !data.replay_cache.lock().unwrap().insert(&signature, timestamp_ms, now) {
+        return Err(ErrorUnauthorized("Request already processed"));
+    }
+
+    Ok(AuthUser {
+        user_id: db_api_key.user_id,
+        api_key_id: Some(db_api_key.id),
+        scopes: db_api_key.scopes.iter().filter_map(|scope| ApiKeyScope::parse(scope)).collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use hmac::{Hmac, KeyInit, Mac};
+    use sha2::Sha256;
+
+    use super::{ReplayCache, signing_payload, verify_signature};
+
+    fn sign(secret: &[u8], payload: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+        mac.update(payload);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    #[test]
+    fn replay_cache_accepts_each_signature_once() {
+        let mut replay_cache = ReplayCache::default();
+
+        assert!(replay_cache.insert("abc", 1_000, 1_000));
+        assert!(!replay_cache.insert("abc", 1_000, 2_000));
+        assert!(replay_cache.insert("def", 1_000, 2_000));
+    }
+
+    #[test]
+    fn replay_cache_forgets_signatures_outside_the_window() {
+        let mut replay_cache = ReplayCache::default();
+
+        assert!(replay_cache.insert("abc", 1_000, 1_000));
+        //the timestamp check already rejects a request this old, the entry is no longer needed
+        assert!(replay_cache.insert("abc", 1_000, 7_000));
+    }
+
+    #[test]
+    fn verify_signature_accepts_the_signed_payload_only() {
+        let secret = b"secret";
+        let payload = signing_payload("1700000000000", "POST", "/order", br#"{"side":"Bid"}"#);
+        let signature = sign(secret, &payload);
+
+        assert!(verify_signature(secret, &payload, &signature));
+        assert!(verify_signature(secret, &payload, &signature.to_uppercase()));
+
+        let tampered = signing_payload("1700000000000", "POST", "/order", br#"{"side":"Ask"}"#);
+        assert!(!verify_signature(secret, &tampered, &signature));
+        assert!(!verify_signature(b"other secret", &payload, &signature));
+        assert!(!verify_signature(secret, &payload, "not hex"));
+    }
+}
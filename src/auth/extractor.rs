@@ -0,0 +1,49 @@
+use std::future::{Ready, ready};
+
+use actix_web::{FromRequest, HttpMessage, HttpRequest, dev::Payload, error::ErrorUnauthorized};
+use uuid::Uuid;
+
+use crate::auth::{ApiKeyScope, verify_token};
+
+//the authenticated caller, either from a signed API key request or the `Authorization: Bearer <jwt>` header.
+//handlers use its user_id instead of anything the client sends in the body
+#[derive(Clone)]
+pub struct AuthUser {
+    pub user_id: Uuid,
+    //None for session tokens
+    pub api_key_id: Option<Uuid>,
+    pub scopes: Vec<ApiKeyScope>
+}
+
+impl AuthUser {
+    pub fn has_scope(&self, scope: ApiKeyScope) -> bool {
+        self.scopes.contains(&scope)
+    }
+}
+
+impl FromRequest for AuthUser {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        //already authenticated by the api key middleware
+        if let Some(auth_user) = req.extensions().get::<AuthUser>() {
+            return ready(Ok(auth_user.clone()));
+        }
+
+        let token = req.headers()
+            .get("Authorization")
+            .and_then(|header| header.to_str().ok())
+            .and_then(|header| header.strip_prefix("Bearer "));
+
+        let Some(token) = token else {
+            return ready(Err(ErrorUnauthorized("Missing bearer token")));
+        };
+
+        //sessions carry the scopes they were issued with
+        match verify_token(token) {
+            Ok(claims) => ready(Ok(AuthUser { user_id: claims.sub, api_key_id: None, scopes: claims.scopes })),
+            Err(_) => ready(Err(ErrorUnauthorized("Invalid or expired token")))
+        }
+    }
+}
@@ -0,0 +1,8 @@
+pub mod password;
+pub use password::*;
+pub mod token;
+pub use token::*;
+pub mod extractor;
+pub use extractor::*;
+pub mod api_key;
+pub use api_key::*;
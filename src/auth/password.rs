@@ -0,0 +1,37 @@
+use std::sync::OnceLock;
+
+use argon2::{Argon2, PasswordHasher, PasswordVerifier};
+
+//argon2id with a random salt, stored in PHC string format
+pub fn hash_password(password: &str) -> anyhow::Result<String> {
+    let password_hash = Argon2::default()
+        .hash_password(password.as_bytes())
+        .map_err(|e| anyhow::anyhow!("Failed to hash password: {}", e))?;
+
+    Ok(password_hash.to_string())
+}
+
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    Argon2::default()
+        .verify_password(password.as_bytes(), password_hash)
+        .is_ok()
+}
+
+//checked instead of a real hash for unknown emails, so a login takes as long whether or not the email is registered
+fn dummy_password_hash() -> &'static str {
+    static DUMMY_PASSWORD_HASH: OnceLock<String> = OnceLock::new();
+    DUMMY_PASSWORD_HASH.get_or_init(|| hash_password("dummy password").unwrap_or_default())
+}
+
+//false when there is no hash, after doing the same work as a wrong password
+pub fn verify_login(password: &str, password_hash: Option<&str>) -> bool {
+    match password_hash {
+        Some(password_hash) => {
+            verify_password(password, password_hash)
+        }
+        None => {
+            verify_password(password, dummy_password_hash());
+            false
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use std::env;
+
+use chrono::Utc;
+use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::auth::ApiKeyScope;
+
+const TOKEN_TTL_SECS: i64 = 24 * 60 * 60;
+
+//what a logged in session can do. withdrawals need an api key created with that scope
+const SESSION_SCOPES: [ApiKeyScope; 2] = [ApiKeyScope::Read, ApiKeyScope::Trade];
+
+#[derive(Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: Uuid,
+    pub exp: i64,
+    pub scopes: Vec<ApiKeyScope>
+}
+
+//tokens are HS256 signed with JWT_SECRET from the environment
+fn jwt_secret() -> anyhow::Result<String> {
+    match env::var("JWT_SECRET") {
+        Ok(secret) if !secret.is_empty() => Ok(secret),
+        _ => Err(anyhow::anyhow!("JWT_SECRET is not set"))
+    }
+}
+
+pub fn issue_token(user_id: Uuid) -> anyhow::Result<String> {
+    let claims = Claims {
+        sub: user_id,
+        exp: Utc::now().timestamp() + TOKEN_TTL_SECS,
+        scopes: SESSION_SCOPES.to_vec()
+    };
+
+    let token = encode(&Header::default(), &claims, &EncodingKey::from_secret(jwt_secret()?.as_bytes()))?;
+    Ok(token)
+}
+
+//checks signature and expiry, returns the user the token was issued to and its scopes
+pub fn verify_token(token: &str) -> anyhow::Result<Claims> {
+    let token_data = decode::<Claims>(token, &DecodingKey::from_secret(jwt_secret()?.as_bytes()), &Validation::default())?;
+    Ok(token_data.claims)
+}
@@ -0,0 +1,84 @@
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+use crate::db::schema::DbApiKey;
+
+pub async fn create_api_key(
+    pool: &Pool<Postgres>,
+    user_id: Uuid,
+    api_key: &str,
+    encrypted_secret: &str,
+    label: Option<&str>,
+    scopes: &[String],
+    allowed_ips: &[String]
+) -> anyhow::Result<DbApiKey> {
+    let db_api_key = sqlx::query_as!(
+        DbApiKey,
+        r#"
+        INSERT INTO api_keys (user_id, api_key, encrypted_secret, label, scopes, allowed_ips)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id, user_id, api_key, encrypted_secret, label, scopes, allowed_ips, created_at, revoked_at
+        "#,
+        user_id,
+        api_key,
+        encrypted_secret,
+        label,
+        scopes,
+        allowed_ips
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(db_api_key)
+}
+
+//revoked keys are never returned
+pub async fn get_active_api_key(pool: &Pool<Postgres>, api_key: &str) -> anyhow::Result<Option<DbApiKey>> {
+    let db_api_key = sqlx::query_as!(
+        DbApiKey,
+        r#"
+        SELECT id, user_id, api_key, encrypted_secret, label, scopes, allowed_ips, created_at, revoked_at
+        FROM api_keys
+        WHERE api_key = $1 AND revoked_at IS NULL
+        "#,
+        api_key
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(db_api_key)
+}
+
+pub async fn get_user_api_keys(pool: &Pool<Postgres>, user_id: Uuid) -> anyhow::Result<Vec<DbApiKey>> {
+    let db_api_keys = sqlx::query_as!(
+        DbApiKey,
+        r#"
+        SELECT id, user_id, api_key, encrypted_secret, label, scopes, allowed_ips, created_at, revoked_at
+        FROM api_keys
+        WHERE user_id = $1
+        ORDER BY created_at DESC
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(db_api_keys)
+}
+
+//false if the key does not exist, belongs to someone else or is already revoked
+pub async fn revoke_api_key(pool: &Pool<Postgres>, user_id: Uuid, id: Uuid) -> anyhow::Result<bool> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE api_keys
+        SET revoked_at = NOW()
+        WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+        id,
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
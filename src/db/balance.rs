@@ -1,8 +1,18 @@
 use bigdecimal::BigDecimal;
-use sqlx::{Pool, Postgres};
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, Pool, Postgres};
 use uuid::Uuid;
 
-use crate::{db::schema::DbUserBalance, service::UserBalance};
+use crate::{db::schema::{DbLedgerEntry, DbUserBalance}, service::{Asset, LedgerReason, UpdateBalanceArgs, UserBalance}};
+
+//filters for one asset's ledger, None matches everything
+pub struct LedgerFilter {
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    //id of the last entry of the previous page
+    pub before: Option<i64>,
+    pub limit: i64
+}
 
 pub async fn get_all_user_balance(pool: &Pool<Postgres>) -> anyhow::Result<Vec<UserBalance>> {
     let db_balances = sqlx::query_as!(
@@ -39,7 +49,7 @@ pub async fn get_all_user_balance(pool: &Pool<Postgres>) -> anyhow::Result<Vec<U
 }
 
 
-pub async fn create_user_balance(pool: &Pool<Postgres>, user_id: Uuid) -> anyhow::Result<UserBalance> {
+pub async fn create_user_balance(executor: impl PgExecutor<'_>, user_id: Uuid) -> anyhow::Result<UserBalance> {
     let db_balance = sqlx::query_as!(
         DbUserBalance,
         r#"
@@ -69,7 +79,7 @@ pub async fn create_user_balance(pool: &Pool<Postgres>, user_id: Uuid) -> anyhow
         "#,
         user_id
     )
-    .fetch_one(pool)
+    .fetch_one(executor)
     .await?;
 
     let user_balance  = UserBalance { 
@@ -84,9 +94,8 @@ pub async fn create_user_balance(pool: &Pool<Postgres>, user_id: Uuid) -> anyhow
     Ok(user_balance)
 }
 
-pub async fn update_user_balance(pool: &Pool<Postgres>, updated_balance: UserBalance) -> anyhow::Result<()> {
-    let db_balance = sqlx::query_as!(
-        DbUserBalance,
+pub async fn update_user_balance(executor: impl PgExecutor<'_>, updated_balance: UserBalance) -> anyhow::Result<()> {
+    sqlx::query!(
         r#"
         UPDATE user_balance
         SET
@@ -103,8 +112,142 @@ pub async fn update_user_balance(pool: &Pool<Postgres>, updated_balance: UserBal
         updated_balance.locked_quote_qty,
         updated_balance.user_id
     )
-    .fetch_one(pool)
+    .execute(executor)
     .await?;
 
     Ok(())
-} 
\ No newline at end of file
+}
+
+//one statement for many users, callers pass at most one balance per user
+pub async fn update_user_balances(executor: impl PgExecutor<'_>, updated_balances: &[UserBalance]) -> anyhow::Result<u64> {
+    let user_ids: Vec<Uuid> = updated_balances.iter().map(|balance| balance.user_id).collect();
+    let free_base_qtys: Vec<BigDecimal> = updated_balances.iter().map(|balance| balance.free_base_qty.clone()).collect();
+    let free_quote_qtys: Vec<BigDecimal> = updated_balances.iter().map(|balance| balance.free_quote_qty.clone()).collect();
+    let locked_base_qtys: Vec<BigDecimal> = updated_balances.iter().map(|balance| balance.locked_base_qty.clone()).collect();
+    let locked_quote_qtys: Vec<BigDecimal> = updated_balances.iter().map(|balance| balance.locked_quote_qty.clone()).collect();
+
+    let result = sqlx::query!(
+        r#"
+        UPDATE user_balance b
+        SET
+            free_base_qty = u.free_base_qty,
+            free_quote_qty = u.free_quote_qty,
+            locked_base_qty = u.locked_base_qty,
+            locked_quote_qty = u.locked_quote_qty,
+            updated_at = NOW()
+        FROM UNNEST($1::UUID[], $2::NUMERIC[], $3::NUMERIC[], $4::NUMERIC[], $5::NUMERIC[])
+            AS u(user_id, free_base_qty, free_quote_qty, locked_base_qty, locked_quote_qty)
+        WHERE b.user_id = u.user_id
+        "#,
+        &user_ids,
+        &free_base_qtys,
+        &free_quote_qtys,
+        &locked_base_qtys,
+        &locked_quote_qtys
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+ 
+//records how each balance differs from the one before it, in order. the first balance of a user is
+//compared with user_balance, so this must run before user_balance is updated, in the same transaction
+pub async fn insert_balance_ledger(executor: impl PgExecutor<'_>, updates: &[UpdateBalanceArgs]) -> anyhow::Result<u64> {
+    let user_ids: Vec<Uuid> = updates.iter().map(|args| args.balance.user_id).collect();
+    let free_base_qtys: Vec<BigDecimal> = updates.iter().map(|args| args.balance.free_base_qty.clone()).collect();
+    let free_quote_qtys: Vec<BigDecimal> = updates.iter().map(|args| args.balance.free_quote_qty.clone()).collect();
+    let locked_base_qtys: Vec<BigDecimal> = updates.iter().map(|args| args.balance.locked_base_qty.clone()).collect();
+    let locked_quote_qtys: Vec<BigDecimal> = updates.iter().map(|args| args.balance.locked_quote_qty.clone()).collect();
+    let reasons: Vec<LedgerReason> = updates.iter().map(|args| args.reason).collect();
+    let reference_ids: Vec<Uuid> = updates.iter().map(|args| args.reference_id).collect();
+
+    let result = sqlx::query!(
+        r#"
+        WITH u AS (
+            SELECT *
+            FROM UNNEST($1::UUID[], $2::NUMERIC[], $3::NUMERIC[], $4::NUMERIC[], $5::NUMERIC[], $6::VARCHAR[], $7::UUID[]) WITH ORDINALITY
+                AS u(user_id, free_base_qty, free_quote_qty, locked_base_qty, locked_quote_qty, reason, reference_id, n)
+        ),
+        c AS (
+            SELECT
+                u.n,
+                u.user_id,
+                u.free_base_qty,
+                u.free_quote_qty,
+                u.locked_base_qty,
+                u.locked_quote_qty,
+                u.reason,
+                u.reference_id,
+                COALESCE(LAG(u.free_base_qty) OVER w, b.free_base_qty) AS prev_free_base_qty,
+                COALESCE(LAG(u.free_quote_qty) OVER w, b.free_quote_qty) AS prev_free_quote_qty,
+                COALESCE(LAG(u.locked_base_qty) OVER w, b.locked_base_qty) AS prev_locked_base_qty,
+                COALESCE(LAG(u.locked_quote_qty) OVER w, b.locked_quote_qty) AS prev_locked_quote_qty
+            FROM u
+            JOIN user_balance b ON b.user_id = u.user_id
+            WINDOW w AS (PARTITION BY u.user_id ORDER BY u.n)
+        ),
+        e AS (
+            SELECT n, user_id, 'Base' AS asset, free_base_qty - prev_free_base_qty AS free_change, locked_base_qty - prev_locked_base_qty AS locked_change, free_base_qty AS free, locked_base_qty AS locked, reason, reference_id
+            FROM c
+            UNION ALL
+            SELECT n, user_id, 'Quote' AS asset, free_quote_qty - prev_free_quote_qty AS free_change, locked_quote_qty - prev_locked_quote_qty AS locked_change, free_quote_qty AS free, locked_quote_qty AS locked, reason, reference_id
+            FROM c
+        )
+        INSERT INTO balance_ledger (user_id, asset, free_change, locked_change, free, locked, reason, reference_id)
+        SELECT user_id, asset, free_change, locked_change, free, locked, reason, reference_id
+        FROM e
+        WHERE free_change <> 0 OR locked_change <> 0
+        ORDER BY n, asset
+        "#,
+        &user_ids,
+        &free_base_qtys,
+        &free_quote_qtys,
+        &locked_base_qtys,
+        &locked_quote_qtys,
+        &reasons as &[LedgerReason],
+        &reference_ids
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+//newest first
+pub async fn get_balance_ledger(pool: &Pool<Postgres>, user_id: Uuid, asset: Asset, filter: &LedgerFilter) -> anyhow::Result<Vec<DbLedgerEntry>> {
+    let db_entries = sqlx::query_as!(
+        DbLedgerEntry,
+        r#"
+        SELECT
+            id,
+            user_id,
+            asset AS "asset: Asset",
+            free_change,
+            locked_change,
+            free,
+            locked,
+            reason AS "reason: LedgerReason",
+            reference_id,
+            created_at
+        FROM balance_ledger
+        WHERE user_id = $1
+            AND asset = $2
+            AND ($3::TIMESTAMPTZ IS NULL OR created_at >= $3)
+            AND ($4::TIMESTAMPTZ IS NULL OR created_at < $4)
+            AND ($5::BIGINT IS NULL OR id < $5)
+        ORDER BY id DESC
+        LIMIT $6
+        "#,
+        user_id,
+        asset as Asset,
+        filter.start,
+        filter.end,
+        filter.before,
+        filter.limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(db_entries)
+}
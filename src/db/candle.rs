@@ -0,0 +1,54 @@
+use chrono::{DateTime, Utc};
+use sqlx::{Pool, Postgres};
+
+use crate::{db::schema::DbCandle, service::{Candle, CandleInterval}};
+
+//candles with open_time in [start, end), oldest first.
+//the view name comes from CandleInterval so it is never user input, hence the unchecked query
+pub async fn get_candles(pool: &Pool<Postgres>, market: &str, interval: CandleInterval, start: DateTime<Utc>, end: DateTime<Utc>, limit: i64) -> anyhow::Result<Vec<Candle>> {
+    let query = format!(
+        r#"
+        SELECT
+            bucket,
+            open,
+            high,
+            low,
+            close,
+            volume,
+            quote_volume,
+            trade_count
+        FROM {}
+        WHERE market = $1 AND bucket >= $2 AND bucket < $3
+        ORDER BY bucket ASC
+        LIMIT $4
+        "#,
+        interval.view()
+    );
+
+    let db_candles = sqlx::query_as::<_, DbCandle>(&query)
+        .bind(market)
+        .bind(start)
+        .bind(end)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?;
+
+    let candles = db_candles.into_iter()
+        .map(|db_candle| {
+            let open_time = db_candle.bucket.timestamp_millis();
+            Candle {
+                open_time: open_time,
+                close_time: open_time + interval.millis() - 1,
+                open: db_candle.open,
+                high: db_candle.high,
+                low: db_candle.low,
+                close: db_candle.close,
+                volume: db_candle.volume,
+                quote_volume: db_candle.quote_volume,
+                trade_count: db_candle.trade_count
+            }
+        })
+        .collect();
+
+    Ok(candles)
+}
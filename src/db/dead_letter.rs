@@ -0,0 +1,18 @@
+use sqlx::{Pool, Postgres};
+
+pub async fn create_dead_letter(pool: &Pool<Postgres>, worker: &str, payload: serde_json::Value, error: &str, attempts: i32) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO dead_letters (worker, payload, error, attempts)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        worker,
+        payload,
+        error,
+        attempts
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+
+use sqlx::{Pool, Postgres};
+
+use crate::{db::{get_user_fee_tiers, schema::{DbFeeSchedule, DbFeeTier, DbUserFeeOverride}}, service::{FeeRates, FeeSchedule, FeeTier}};
+
+pub async fn get_fee_schedule(pool: &Pool<Postgres>, market: &str) -> anyhow::Result<FeeSchedule> {
+    let db_schedule = sqlx::query_as!(
+        DbFeeSchedule,
+        r#"
+        SELECT
+            market,
+            maker_rate,
+            taker_rate,
+            fee_account_id,
+            created_at,
+            updated_at
+        FROM fee_schedules
+        WHERE market = $1
+        "#,
+        market
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    //no schedule configured for this market, trade without fees
+    let Some(db_schedule) = db_schedule else {
+        return Ok(FeeSchedule::default(market));
+    };
+
+    let db_tiers = sqlx::query_as!(
+        DbFeeTier,
+        r#"
+        SELECT
+            market,
+            tier,
+            min_volume,
+            maker_rate,
+            taker_rate
+        FROM fee_tiers
+        WHERE market = $1
+        ORDER BY min_volume ASC
+        "#,
+        market
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let db_overrides = sqlx::query_as!(
+        DbUserFeeOverride,
+        r#"
+        SELECT
+            user_id,
+            market,
+            maker_rate,
+            taker_rate,
+            created_at
+        FROM user_fee_overrides
+        WHERE market = $1
+        "#,
+        market
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut tiers: Vec<FeeTier> = Vec::new();
+    for tier in db_tiers.iter() {
+        tiers.push(FeeTier {
+            tier: tier.tier,
+            min_volume: tier.min_volume.clone(),
+            rates: FeeRates {
+                maker_rate: tier.maker_rate.clone(),
+                taker_rate: tier.taker_rate.clone()
+            }
+        });
+    }
+
+    let mut overrides: HashMap<_, FeeRates> = HashMap::new();
+    for fee_override in db_overrides.iter() {
+        overrides.insert(fee_override.user_id, FeeRates {
+            maker_rate: fee_override.maker_rate.clone(),
+            taker_rate: fee_override.taker_rate.clone()
+        });
+    }
+
+    //tiers assigned by the last volume refresh
+    let user_tiers = get_user_fee_tiers(pool, market).await?;
+
+    let fee_schedule = FeeSchedule {
+        market: db_schedule.market,
+        base_rates: FeeRates {
+            maker_rate: db_schedule.maker_rate,
+            taker_rate: db_schedule.taker_rate
+        },
+        fee_account_id: Some(db_schedule.fee_account_id),
+        tiers: tiers,
+        user_tiers: user_tiers,
+        overrides: overrides
+    };
+
+    Ok(fee_schedule)
+}
@@ -0,0 +1,79 @@
+use chrono::{DateTime, Utc};
+use sqlx::{Pool, Postgres};
+
+use crate::{db::schema::DbMarket, service::{MarketConfig, MarketState, MatchingAlgorithmKind, SlippageReference}};
+
+pub async fn get_market_config(pool: &Pool<Postgres>, market: &str) -> anyhow::Result<MarketConfig> {
+    let db_market = sqlx::query_as!(
+        DbMarket,
+        r#"
+        SELECT
+            market,
+            state AS "state: MarketState",
+            state_until,
+            max_slippage_pct,
+            slippage_reference AS "slippage_reference: SlippageReference",
+            price_band_pct,
+            breaker_move_pct,
+            breaker_window_secs,
+            breaker_cooldown_secs,
+            allow_cancels_when_halted,
+            auction_duration_secs,
+            matching_algorithm AS "matching_algorithm: MatchingAlgorithmKind",
+            lot_size,
+            tick_size,
+            created_at,
+            updated_at
+        FROM markets
+        WHERE market = $1
+        "#,
+        market
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    //no row for this market, trade without limits
+    let Some(db_market) = db_market else {
+        return Ok(MarketConfig::default(market));
+    };
+
+    let market_config = MarketConfig {
+        market: db_market.market,
+        state: db_market.state,
+        state_until: db_market.state_until.map(|until| until.timestamp_millis()),
+        max_slippage_pct: db_market.max_slippage_pct,
+        slippage_reference: db_market.slippage_reference,
+        price_band_pct: db_market.price_band_pct,
+        breaker_move_pct: db_market.breaker_move_pct,
+        breaker_window_secs: db_market.breaker_window_secs,
+        breaker_cooldown_secs: db_market.breaker_cooldown_secs,
+        allow_cancels_when_halted: db_market.allow_cancels_when_halted,
+        auction_duration_secs: db_market.auction_duration_secs,
+        matching_algorithm: db_market.matching_algorithm,
+        lot_size: db_market.lot_size,
+        tick_size: db_market.tick_size
+    };
+
+    Ok(market_config)
+}
+
+//markets without a row yet get one with default configuration. `until` is in milliseconds
+pub async fn update_market_state(pool: &Pool<Postgres>, market: &str, state: MarketState, until: Option<i64>) -> anyhow::Result<()> {
+    let until = until.and_then(DateTime::<Utc>::from_timestamp_millis);
+
+    sqlx::query!(
+        r#"
+        INSERT INTO markets (market, state, state_until)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (market) DO UPDATE
+        SET state = EXCLUDED.state, state_until = EXCLUDED.state_until, updated_at = NOW()
+        "#,
+        market,
+        state as MarketState,
+        until
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS user_fee_overrides;
+DROP TABLE IF EXISTS fee_tiers;
+DROP TABLE IF EXISTS fee_schedules;
@@ -0,0 +1,41 @@
+CREATE TABLE fee_schedules (
+    market VARCHAR(32) PRIMARY KEY,
+
+    maker_rate NUMERIC(38,18) NOT NULL DEFAULT 0 CHECK (maker_rate >= 0),
+    taker_rate NUMERIC(38,18) NOT NULL DEFAULT 0 CHECK (taker_rate >= 0),
+
+    fee_account_id UUID NOT NULL
+        REFERENCES users(id),
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE fee_tiers (
+    market VARCHAR(32) NOT NULL
+        REFERENCES fee_schedules(market)
+        ON DELETE CASCADE,
+    tier INTEGER NOT NULL,
+
+    min_volume NUMERIC(38,18) NOT NULL DEFAULT 0,
+    maker_rate NUMERIC(38,18) NOT NULL CHECK (maker_rate >= 0),
+    taker_rate NUMERIC(38,18) NOT NULL CHECK (taker_rate >= 0),
+
+    PRIMARY KEY (market, tier)
+);
+
+CREATE TABLE user_fee_overrides (
+    user_id UUID NOT NULL
+        REFERENCES users(id)
+        ON DELETE CASCADE,
+    market VARCHAR(32) NOT NULL
+        REFERENCES fee_schedules(market)
+        ON DELETE CASCADE,
+
+    maker_rate NUMERIC(38,18) NOT NULL CHECK (maker_rate >= 0),
+    taker_rate NUMERIC(38,18) NOT NULL CHECK (taker_rate >= 0),
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    PRIMARY KEY (user_id, market)
+);
@@ -0,0 +1,3 @@
+ALTER TABLE trades
+    DROP COLUMN IF EXISTS buyer_fee,
+    DROP COLUMN IF EXISTS seller_fee;
@@ -0,0 +1,3 @@
+ALTER TABLE trades
+    ADD COLUMN buyer_fee  NUMERIC(38,18) NOT NULL DEFAULT 0,
+    ADD COLUMN seller_fee NUMERIC(38,18) NOT NULL DEFAULT 0;
@@ -0,0 +1,14 @@
+CREATE TABLE user_trading_volume (
+    user_id UUID NOT NULL
+        REFERENCES users(id)
+        ON DELETE CASCADE,
+    market VARCHAR(32) NOT NULL,
+
+    -- quote volume traded over the trailing 30 days
+    quote_volume NUMERIC(38,18) NOT NULL DEFAULT 0,
+    tier INTEGER,
+
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    PRIMARY KEY (user_id, market)
+);
@@ -0,0 +1,2 @@
+ALTER TABLE trades
+    DROP COLUMN IF EXISTS market;
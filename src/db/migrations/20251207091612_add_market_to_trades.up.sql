@@ -0,0 +1,3 @@
+-- volume and fee tiers are per market. trades from before this stay NULL and count towards no market
+ALTER TABLE trades
+    ADD COLUMN market VARCHAR(32);
@@ -0,0 +1,10 @@
+DROP INDEX IF EXISTS trades_market_seq_idx;
+DROP INDEX IF EXISTS trades_seller_user_id_idx;
+DROP INDEX IF EXISTS trades_buyer_user_id_idx;
+
+ALTER TABLE trades
+    DROP COLUMN IF EXISTS buyer_user_id,
+    DROP COLUMN IF EXISTS seller_user_id,
+    DROP COLUMN IF EXISTS maker_order_id,
+    DROP COLUMN IF EXISTS taker_side,
+    DROP COLUMN IF EXISTS seq;
@@ -0,0 +1,22 @@
+ALTER TABLE trades
+    ADD COLUMN buyer_user_id  UUID,
+    ADD COLUMN seller_user_id UUID,
+    ADD COLUMN maker_order_id UUID,
+    ADD COLUMN taker_side     VARCHAR(10) CHECK (taker_side IN ('Bid', 'Ask')),
+    ADD COLUMN seq            BIGINT;
+
+-- backfill existing trades from their orders, the older order is taken as the maker.
+-- market and seq are unknown for these rows and stay NULL
+UPDATE trades t
+SET
+    buyer_user_id  = b.user_id,
+    seller_user_id = s.user_id,
+    maker_order_id = CASE WHEN b.created_at <= s.created_at THEN b.id ELSE s.id END,
+    taker_side     = CASE WHEN b.created_at <= s.created_at THEN 'Ask' ELSE 'Bid' END
+FROM orders b, orders s
+WHERE b.id = t.buy_order_id
+    AND s.id = t.sell_order_id;
+
+CREATE INDEX trades_buyer_user_id_idx ON trades (buyer_user_id, created_at DESC);
+CREATE INDEX trades_seller_user_id_idx ON trades (seller_user_id, created_at DESC);
+CREATE INDEX trades_market_seq_idx ON trades (market, seq DESC);
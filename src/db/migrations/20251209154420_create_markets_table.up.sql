@@ -0,0 +1,14 @@
+CREATE TABLE markets (
+    market VARCHAR(32) PRIMARY KEY,
+
+    -- how far (in percent) a market order may sweep from its reference price, NULL for no limit
+    max_slippage_pct NUMERIC(38,18) CHECK (max_slippage_pct > 0),
+    slippage_reference VARCHAR(10) NOT NULL DEFAULT 'BestPrice'
+        CHECK (slippage_reference IN ('BestPrice', 'LastTrade')),
+
+    -- how far (in percent) a limit price may be from the last trade price, NULL for no band
+    price_band_pct NUMERIC(38,18) CHECK (price_band_pct > 0),
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
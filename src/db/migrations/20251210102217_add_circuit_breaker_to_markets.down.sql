@@ -0,0 +1,5 @@
+ALTER TABLE markets
+    DROP COLUMN IF EXISTS breaker_move_pct,
+    DROP COLUMN IF EXISTS breaker_window_secs,
+    DROP COLUMN IF EXISTS breaker_cooldown_secs,
+    DROP COLUMN IF EXISTS allow_cancels_when_halted;
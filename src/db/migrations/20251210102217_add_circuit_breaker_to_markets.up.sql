@@ -0,0 +1,6 @@
+ALTER TABLE markets
+    -- halt trading when the price moves this many percent within the window, NULL to disable
+    ADD COLUMN breaker_move_pct NUMERIC(38,18) CHECK (breaker_move_pct > 0),
+    ADD COLUMN breaker_window_secs INTEGER NOT NULL DEFAULT 60 CHECK (breaker_window_secs > 0),
+    ADD COLUMN breaker_cooldown_secs INTEGER NOT NULL DEFAULT 300 CHECK (breaker_cooldown_secs > 0),
+    ADD COLUMN allow_cancels_when_halted BOOLEAN NOT NULL DEFAULT TRUE;
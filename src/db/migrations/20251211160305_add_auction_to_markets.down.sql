@@ -0,0 +1,2 @@
+ALTER TABLE markets
+    DROP COLUMN IF EXISTS auction_duration_secs;
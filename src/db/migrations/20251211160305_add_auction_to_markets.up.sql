@@ -0,0 +1,3 @@
+ALTER TABLE markets
+    -- length of the call auction run before trading resumes after a halt, 0 to resume directly
+    ADD COLUMN auction_duration_secs INTEGER NOT NULL DEFAULT 0 CHECK (auction_duration_secs >= 0);
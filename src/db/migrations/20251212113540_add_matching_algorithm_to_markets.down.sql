@@ -0,0 +1,3 @@
+ALTER TABLE markets
+    DROP COLUMN IF EXISTS matching_algorithm,
+    DROP COLUMN IF EXISTS lot_size;
@@ -0,0 +1,5 @@
+ALTER TABLE markets
+    ADD COLUMN matching_algorithm VARCHAR(16) NOT NULL DEFAULT 'Fifo'
+        CHECK (matching_algorithm IN ('Fifo', 'ProRata', 'FifoProRata')),
+    -- smallest quantity pro-rata allocation hands out, residual lots go out in time priority
+    ADD COLUMN lot_size NUMERIC(38,18) NOT NULL DEFAULT 0.000000000000000001 CHECK (lot_size > 0);
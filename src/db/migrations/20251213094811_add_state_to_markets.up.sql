@@ -0,0 +1,4 @@
+ALTER TABLE markets
+    -- lifecycle state, changed by admins and persisted by the engine on every transition
+    ADD COLUMN state VARCHAR(16) NOT NULL DEFAULT 'Trading'
+        CHECK (state IN ('Listing', 'PreOpen', 'Trading', 'Halted', 'Delisted'));
@@ -0,0 +1,20 @@
+CREATE TABLE api_keys (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+
+    -- public identifier sent in X-API-KEY
+    api_key TEXT UNIQUE NOT NULL,
+    -- the secret is the HMAC key, so it is kept encrypted under API_KEY_ENCRYPTION_KEY: hex of the
+    -- 12 byte AES-256-GCM nonce followed by the ciphertext. the secret is only shown once at creation
+    encrypted_secret TEXT NOT NULL,
+
+    label TEXT,
+    scopes TEXT[] NOT NULL CHECK (scopes <@ ARRAY['read', 'trade', 'withdraw']::TEXT[]),
+    -- empty allows any address
+    allowed_ips TEXT[] NOT NULL DEFAULT '{}',
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    revoked_at TIMESTAMPTZ
+);
+
+CREATE INDEX api_keys_user_id_idx ON api_keys(user_id);
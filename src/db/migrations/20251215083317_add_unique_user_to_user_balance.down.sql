@@ -0,0 +1,2 @@
+ALTER TABLE user_balance
+    DROP CONSTRAINT IF EXISTS user_balance_user_id_key;
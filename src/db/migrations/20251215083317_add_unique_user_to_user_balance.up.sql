@@ -0,0 +1,9 @@
+-- one balance row per user, the engine keys balances by user_id
+ALTER TABLE user_balance
+    ADD CONSTRAINT user_balance_user_id_key UNIQUE (user_id);
+
+-- accounts created before signup provisioned balances
+INSERT INTO user_balance (user_id)
+SELECT u.id
+FROM users u
+WHERE NOT EXISTS (SELECT 1 FROM user_balance b WHERE b.user_id = u.id);
@@ -0,0 +1,12 @@
+-- worker events that could not be persisted, kept for inspection and replay
+CREATE TABLE dead_letters (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    worker VARCHAR(32) NOT NULL,
+    payload JSONB NOT NULL,
+    error TEXT NOT NULL,
+    attempts INTEGER NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    resolved_at TIMESTAMPTZ
+);
+
+CREATE INDEX dead_letters_unresolved_idx ON dead_letters(worker, created_at) WHERE resolved_at IS NULL;
@@ -0,0 +1,12 @@
+-- in-memory engine state written on shutdown, for audit and to check the next start against
+CREATE TABLE engine_snapshots (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    market VARCHAR(32) NOT NULL,
+    seq BIGINT NOT NULL,
+    state VARCHAR(16) NOT NULL,
+    orderbook JSONB NOT NULL,
+    balances JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX engine_snapshots_market_created_at_idx ON engine_snapshots(market, created_at DESC);
@@ -0,0 +1,6 @@
+DROP MATERIALIZED VIEW IF EXISTS candles_1m;
+DROP MATERIALIZED VIEW IF EXISTS candles_5m;
+DROP MATERIALIZED VIEW IF EXISTS candles_15m;
+DROP MATERIALIZED VIEW IF EXISTS candles_1h;
+DROP MATERIALIZED VIEW IF EXISTS candles_4h;
+DROP MATERIALIZED VIEW IF EXISTS candles_1d;
@@ -0,0 +1,134 @@
+-- OHLCV candles per market, kept up to date by timescaledb refresh policies.
+-- materialized_only = false merges in trades newer than the last refresh, so the latest candle is live
+
+CREATE MATERIALIZED VIEW candles_1m
+WITH (timescaledb.continuous, timescaledb.materialized_only = false) AS
+SELECT
+    market,
+    time_bucket(INTERVAL '1 minute', created_at) AS bucket,
+    first(price, seq) AS open,
+    max(price) AS high,
+    min(price) AS low,
+    last(price, seq) AS close,
+    sum(quantity) AS volume,
+    sum(price * quantity) AS quote_volume,
+    count(*) AS trade_count
+FROM trades
+WHERE market IS NOT NULL
+GROUP BY market, bucket
+WITH NO DATA;
+
+SELECT add_continuous_aggregate_policy('candles_1m',
+    start_offset => INTERVAL '2 hours',
+    end_offset => INTERVAL '1 minute',
+    schedule_interval => INTERVAL '1 minute');
+
+CREATE MATERIALIZED VIEW candles_5m
+WITH (timescaledb.continuous, timescaledb.materialized_only = false) AS
+SELECT
+    market,
+    time_bucket(INTERVAL '5 minutes', created_at) AS bucket,
+    first(price, seq) AS open,
+    max(price) AS high,
+    min(price) AS low,
+    last(price, seq) AS close,
+    sum(quantity) AS volume,
+    sum(price * quantity) AS quote_volume,
+    count(*) AS trade_count
+FROM trades
+WHERE market IS NOT NULL
+GROUP BY market, bucket
+WITH NO DATA;
+
+SELECT add_continuous_aggregate_policy('candles_5m',
+    start_offset => INTERVAL '6 hours',
+    end_offset => INTERVAL '5 minutes',
+    schedule_interval => INTERVAL '5 minutes');
+
+CREATE MATERIALIZED VIEW candles_15m
+WITH (timescaledb.continuous, timescaledb.materialized_only = false) AS
+SELECT
+    market,
+    time_bucket(INTERVAL '15 minutes', created_at) AS bucket,
+    first(price, seq) AS open,
+    max(price) AS high,
+    min(price) AS low,
+    last(price, seq) AS close,
+    sum(quantity) AS volume,
+    sum(price * quantity) AS quote_volume,
+    count(*) AS trade_count
+FROM trades
+WHERE market IS NOT NULL
+GROUP BY market, bucket
+WITH NO DATA;
+
+SELECT add_continuous_aggregate_policy('candles_15m',
+    start_offset => INTERVAL '12 hours',
+    end_offset => INTERVAL '15 minutes',
+    schedule_interval => INTERVAL '15 minutes');
+
+CREATE MATERIALIZED VIEW candles_1h
+WITH (timescaledb.continuous, timescaledb.materialized_only = false) AS
+SELECT
+    market,
+    time_bucket(INTERVAL '1 hour', created_at) AS bucket,
+    first(price, seq) AS open,
+    max(price) AS high,
+    min(price) AS low,
+    last(price, seq) AS close,
+    sum(quantity) AS volume,
+    sum(price * quantity) AS quote_volume,
+    count(*) AS trade_count
+FROM trades
+WHERE market IS NOT NULL
+GROUP BY market, bucket
+WITH NO DATA;
+
+SELECT add_continuous_aggregate_policy('candles_1h',
+    start_offset => INTERVAL '2 days',
+    end_offset => INTERVAL '1 hour',
+    schedule_interval => INTERVAL '1 hour');
+
+CREATE MATERIALIZED VIEW candles_4h
+WITH (timescaledb.continuous, timescaledb.materialized_only = false) AS
+SELECT
+    market,
+    time_bucket(INTERVAL '4 hours', created_at) AS bucket,
+    first(price, seq) AS open,
+    max(price) AS high,
+    min(price) AS low,
+    last(price, seq) AS close,
+    sum(quantity) AS volume,
+    sum(price * quantity) AS quote_volume,
+    count(*) AS trade_count
+FROM trades
+WHERE market IS NOT NULL
+GROUP BY market, bucket
+WITH NO DATA;
+
+SELECT add_continuous_aggregate_policy('candles_4h',
+    start_offset => INTERVAL '4 days',
+    end_offset => INTERVAL '4 hours',
+    schedule_interval => INTERVAL '1 hour');
+
+CREATE MATERIALIZED VIEW candles_1d
+WITH (timescaledb.continuous, timescaledb.materialized_only = false) AS
+SELECT
+    market,
+    time_bucket(INTERVAL '1 day', created_at) AS bucket,
+    first(price, seq) AS open,
+    max(price) AS high,
+    min(price) AS low,
+    last(price, seq) AS close,
+    sum(quantity) AS volume,
+    sum(price * quantity) AS quote_volume,
+    count(*) AS trade_count
+FROM trades
+WHERE market IS NOT NULL
+GROUP BY market, bucket
+WITH NO DATA;
+
+SELECT add_continuous_aggregate_policy('candles_1d',
+    start_offset => INTERVAL '7 days',
+    end_offset => INTERVAL '1 day',
+    schedule_interval => INTERVAL '1 hour');
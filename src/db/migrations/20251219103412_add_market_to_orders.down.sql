@@ -0,0 +1,6 @@
+DROP INDEX IF EXISTS orders_open_idx;
+DROP INDEX IF EXISTS orders_user_id_open_idx;
+DROP INDEX IF EXISTS orders_user_id_created_at_idx;
+
+ALTER TABLE orders
+    DROP COLUMN IF EXISTS market;
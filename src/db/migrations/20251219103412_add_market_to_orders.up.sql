@@ -0,0 +1,15 @@
+ALTER TABLE orders
+    ADD COLUMN market VARCHAR(32);
+
+-- orders that traded take the market of their trades, the rest are unknown and stay NULL
+UPDATE orders o
+SET market = t.market
+FROM trades t
+WHERE (t.buy_order_id = o.id OR t.sell_order_id = o.id)
+    AND t.market IS NOT NULL;
+
+-- order history per user, newest first, with id breaking ties for pagination
+CREATE INDEX orders_user_id_created_at_idx ON orders (user_id, created_at DESC, id DESC);
+-- open orders per user, and the engine loading the book at startup
+CREATE INDEX orders_user_id_open_idx ON orders (user_id, created_at DESC, id DESC) WHERE status = 'Open';
+CREATE INDEX orders_open_idx ON orders (created_at) WHERE status = 'Open';
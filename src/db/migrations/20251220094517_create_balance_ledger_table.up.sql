@@ -0,0 +1,23 @@
+-- every change to a user's balance of one asset, written by the balance worker with the balance it changed
+CREATE TABLE balance_ledger (
+    id BIGSERIAL PRIMARY KEY,
+
+    user_id UUID NOT NULL
+        REFERENCES users(id)
+        ON DELETE CASCADE,
+
+    -- 'Base' or 'Quote' of the market
+    asset VARCHAR(8) NOT NULL,
+
+    free_change NUMERIC(38,18) NOT NULL,
+    locked_change NUMERIC(38,18) NOT NULL,
+
+    -- balance after the change
+    free NUMERIC(38,18) NOT NULL,
+    locked NUMERIC(38,18) NOT NULL,
+
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- ledger of one asset per user, newest first
+CREATE INDEX balance_ledger_user_id_asset_idx ON balance_ledger(user_id, asset, id DESC);
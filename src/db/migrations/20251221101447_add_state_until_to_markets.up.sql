@@ -0,0 +1,3 @@
+ALTER TABLE markets
+    -- when a timed state (breaker halt, call auction) ends by itself, NULL while it lasts until an admin ends it
+    ADD COLUMN state_until TIMESTAMPTZ;
@@ -0,0 +1,2 @@
+ALTER TABLE markets
+    DROP COLUMN IF EXISTS tick_size;
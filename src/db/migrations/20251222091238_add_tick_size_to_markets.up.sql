@@ -0,0 +1,3 @@
+ALTER TABLE markets
+    -- limit prices must be whole multiples of the tick size, order quantities of lot_size
+    ADD COLUMN tick_size NUMERIC(38,18) NOT NULL DEFAULT 0.000000000000000001 CHECK (tick_size > 0);
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS orders_market_open_idx;
+CREATE INDEX orders_open_idx ON orders (created_at) WHERE status = 'Open';
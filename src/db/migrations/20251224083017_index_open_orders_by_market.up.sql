@@ -0,0 +1,15 @@
+-- open orders from before orders had a market belong to the only market, when there is just one
+WITH known_markets AS (
+    SELECT market FROM markets
+    UNION
+    SELECT market FROM trades WHERE market IS NOT NULL
+)
+UPDATE orders
+SET market = (SELECT market FROM known_markets)
+WHERE market IS NULL
+    AND status = 'Open'
+    AND (SELECT COUNT(*) FROM known_markets) = 1;
+
+-- the engine loads and reconciles the open orders of its own market
+DROP INDEX IF EXISTS orders_open_idx;
+CREATE INDEX orders_market_open_idx ON orders (market, created_at) WHERE status = 'Open';
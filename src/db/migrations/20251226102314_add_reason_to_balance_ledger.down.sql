@@ -0,0 +1,3 @@
+ALTER TABLE balance_ledger
+    DROP COLUMN IF EXISTS reference_id,
+    DROP COLUMN IF EXISTS reason;
@@ -0,0 +1,7 @@
+ALTER TABLE balance_ledger
+    -- what changed the balance: 'Order' (funds locked for an order, or the unused part released once it executed),
+    -- 'Cancel' (funds released by a cancel), 'Trade' (a fill settled) or 'Fee' (fees credited to the fee account).
+    -- NULL for entries written before the cause was recorded
+    ADD COLUMN reason VARCHAR(16),
+    -- the order for 'Order' and 'Cancel', the trade for 'Trade' and 'Fee'
+    ADD COLUMN reference_id UUID;
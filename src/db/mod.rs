@@ -12,6 +12,20 @@ pub mod order;
 pub use order::*;
 pub mod trade;
 pub use trade::*;
+pub mod fee;
+pub use fee::*;
+pub mod volume;
+pub use volume::*;
+pub mod market;
+pub use market::*;
+pub mod api_key;
+pub use api_key::*;
+pub mod dead_letter;
+pub use dead_letter::*;
+pub mod snapshot;
+pub use snapshot::*;
+pub mod candle;
+pub use candle::*;
 
 #[allow(non_snake_case)]
 pub async fn init_db() -> anyhow::Result<Pool<Postgres>> {
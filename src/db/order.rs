@@ -1,16 +1,31 @@
 use anyhow::Ok;
 use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
 use sqlx::{Pool, Postgres};
+use uuid::Uuid;
 
 use crate::{db::schema::DbOrder, service::{CreateOrderArgs, Order, OrderType, Orderbook, Side, Status}};
 
-pub async fn get_open_orders(pool: &Pool<Postgres>) -> anyhow::Result<Vec<Order>> {
+//filters for a user's orders, None matches everything
+pub struct OrderFilter {
+    pub market: Option<String>,
+    pub side: Option<Side>,
+    pub status: Option<Status>,
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    //(created_at, id) of the last order of the previous page
+    pub before: Option<(DateTime<Utc>, Uuid)>,
+    pub limit: i64
+}
+
+pub async fn get_open_orders(pool: &Pool<Postgres>, market: &str) -> anyhow::Result<Vec<Order>> {
     let db_orders = sqlx::query_as!(
         DbOrder,
         r#"
         SELECT 
             id,
             user_id,
+            market,
             order_type AS "order_type: OrderType",
             price,
             quantity,
@@ -20,9 +35,10 @@ pub async fn get_open_orders(pool: &Pool<Postgres>) -> anyhow::Result<Vec<Order>
             created_at, 
             updated_at
         FROM orders
-        WHERE status = 'Open'
+        WHERE status = 'Open' AND market = $1
         ORDER BY created_at ASC
-        "#
+        "#,
+        market
     ).fetch_all(pool)
     .await?;
 
@@ -35,18 +51,20 @@ pub async fn get_open_orders(pool: &Pool<Postgres>) -> anyhow::Result<Vec<Order>
     Ok(orders)
 }
 
-pub async fn create_order(pool: &Pool<Postgres>, create_order_args: &CreateOrderArgs) -> anyhow::Result<Order> {
+pub async fn create_order(pool: &Pool<Postgres>, market: &str, create_order_args: &CreateOrderArgs) -> anyhow::Result<Order> {
     let db_order = sqlx::query_as!(
         DbOrder,
         r#"
         INSERT INTO orders (
+            id,
             order_type,
             user_id,
             price,
             quantity,
             filled_quantity,
             side,
-            status
+            status,
+            market
         )
         VALUES (
             $1,
@@ -55,11 +73,14 @@ pub async fn create_order(pool: &Pool<Postgres>, create_order_args: &CreateOrder
             $4,
             $5,
             $6,
-            'Open'
+            $7,
+            'Open',
+            $8
         )
         RETURNING 
             id,
             user_id,
+            market,
             order_type AS "order_type: OrderType",
             price,
             quantity,
@@ -69,12 +90,14 @@ pub async fn create_order(pool: &Pool<Postgres>, create_order_args: &CreateOrder
             created_at, 
             updated_at
         "#,
+        create_order_args.order_id,
         create_order_args.order_type as OrderType,
         create_order_args.user_id,
         create_order_args.limit_price,
         create_order_args.base_qty,
         BigDecimal::from(0),
-        create_order_args.side as Side
+        create_order_args.side as Side,
+        market
     )
     .fetch_one(pool)
     .await?;
@@ -85,22 +108,128 @@ pub async fn create_order(pool: &Pool<Postgres>, create_order_args: &CreateOrder
 }
 
 pub async fn update_order(pool: &Pool<Postgres>, updated_order: Order) -> anyhow::Result<()> {
-    let db_order = sqlx::query_as!(
-        DbOrder,
+    sqlx::query!(
         r#"
         UPDATE orders
         SET
-            filled_quantity = $1,
-            status          = $2,
+            quantity        = $1,
+            filled_quantity = $2,
+            status          = $3,
             updated_at      = NOW()
-        WHERE id = $3
+        WHERE id = $4
         "#,
+        updated_order.quantity,
         updated_order.filled_quantity,
         updated_order.status as Status,
         updated_order.id
     )
-    .fetch_one(pool)
+    .execute(pool)
     .await?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+//one statement for many orders, callers pass at most one update per order
+pub async fn update_orders(pool: &Pool<Postgres>, updated_orders: &[Order]) -> anyhow::Result<u64> {
+    let ids: Vec<Uuid> = updated_orders.iter().map(|order| order.id).collect();
+    let quantities: Vec<BigDecimal> = updated_orders.iter().map(|order| order.quantity.clone()).collect();
+    let filled_quantities: Vec<BigDecimal> = updated_orders.iter().map(|order| order.filled_quantity.clone()).collect();
+    let statuses: Vec<Status> = updated_orders.iter().map(|order| order.status).collect();
+
+    let result = sqlx::query!(
+        r#"
+        UPDATE orders o
+        SET
+            quantity        = u.quantity,
+            filled_quantity = u.filled_quantity,
+            status          = u.status,
+            updated_at      = NOW()
+        FROM UNNEST($1::UUID[], $2::NUMERIC[], $3::NUMERIC[], $4::VARCHAR[])
+            AS u(id, quantity, filled_quantity, status)
+        WHERE o.id = u.id
+        "#,
+        &ids,
+        &quantities,
+        &filled_quantities,
+        &statuses as &[Status]
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+//None unless the order belongs to user_id
+pub async fn get_user_order(pool: &Pool<Postgres>, user_id: Uuid, order_id: Uuid) -> anyhow::Result<Option<DbOrder>> {
+    let db_order = sqlx::query_as!(
+        DbOrder,
+        r#"
+        SELECT
+            id,
+            user_id,
+            market,
+            order_type AS "order_type: OrderType",
+            price,
+            quantity,
+            filled_quantity,
+            side AS "side: Side",
+            status AS "status: Status",
+            created_at,
+            updated_at
+        FROM orders
+        WHERE id = $1 AND user_id = $2
+        "#,
+        order_id,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(db_order)
+}
+
+//newest first
+pub async fn get_user_orders(pool: &Pool<Postgres>, user_id: Uuid, filter: &OrderFilter) -> anyhow::Result<Vec<DbOrder>> {
+    let (before_created_at, before_id) = filter.before.unzip();
+
+    let db_orders = sqlx::query_as!(
+        DbOrder,
+        r#"
+        SELECT
+            id,
+            user_id,
+            market,
+            order_type AS "order_type: OrderType",
+            price,
+            quantity,
+            filled_quantity,
+            side AS "side: Side",
+            status AS "status: Status",
+            created_at,
+            updated_at
+        FROM orders
+        WHERE user_id = $1
+            AND ($2::VARCHAR IS NULL OR market = $2)
+            AND ($3::VARCHAR IS NULL OR side = $3)
+            AND ($4::VARCHAR IS NULL OR status = $4)
+            AND ($5::TIMESTAMPTZ IS NULL OR created_at >= $5)
+            AND ($6::TIMESTAMPTZ IS NULL OR created_at < $6)
+            AND ($7::TIMESTAMPTZ IS NULL OR (created_at, id) < ($7, $8::UUID))
+        ORDER BY created_at DESC, id DESC
+        LIMIT $9
+        "#,
+        user_id,
+        filter.market,
+        filter.side as Option<Side>,
+        filter.status as Option<Status>,
+        filter.start,
+        filter.end,
+        before_created_at,
+        before_id,
+        filter.limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(db_orders)
+}
@@ -3,7 +3,7 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::service::{OrderType, Side, Status};
+use crate::service::{Asset, LedgerReason, MarketState, MatchingAlgorithmKind, OrderType, Side, SlippageReference, Status};
 
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -18,6 +18,7 @@ pub struct DbUser {
 pub struct DbOrder {
     pub id: Uuid,
     pub user_id: Uuid,
+    pub market: Option<String>,
     pub order_type: OrderType,
     pub price: BigDecimal,
     pub quantity: BigDecimal,
@@ -43,6 +44,21 @@ pub struct DbUserBalance {
     pub updated_at: DateTime<Utc>,
 }
 
+//free and locked are the balance after the change
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DbLedgerEntry {
+    pub id: i64,
+    pub user_id: Uuid,
+    pub asset: Asset,
+    pub free_change: BigDecimal,
+    pub locked_change: BigDecimal,
+    pub free: BigDecimal,
+    pub locked: BigDecimal,
+    pub reason: Option<LedgerReason>,
+    pub reference_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DbTrade {
     pub id: Uuid,
@@ -50,5 +66,101 @@ pub struct DbTrade {
     pub sell_order_id: Uuid,
     pub price: BigDecimal,
     pub quantity: BigDecimal,
+    pub buyer_fee: BigDecimal,
+    pub seller_fee: BigDecimal,
+    pub market: Option<String>,
+    pub buyer_user_id: Uuid,
+    pub seller_user_id: Uuid,
+    pub maker_order_id: Uuid,
+    pub taker_side: Side,
+    pub seq: Option<i64>,
     pub created_at: DateTime<Utc>
-}
\ No newline at end of file
+}
+
+//one side of a trade, as seen by the user on that side
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DbFill {
+    pub trade_id: Uuid,
+    pub order_id: Uuid,
+    pub market: Option<String>,
+    pub seq: Option<i64>,
+    pub side: Side,
+    pub price: BigDecimal,
+    pub quantity: BigDecimal,
+    pub fee: BigDecimal,
+    pub is_maker: bool,
+    pub created_at: DateTime<Utc>
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DbFeeSchedule {
+    pub market: String,
+    pub maker_rate: BigDecimal,
+    pub taker_rate: BigDecimal,
+    pub fee_account_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DbFeeTier {
+    pub market: String,
+    pub tier: i32,
+    pub min_volume: BigDecimal,
+    pub maker_rate: BigDecimal,
+    pub taker_rate: BigDecimal
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DbUserFeeOverride {
+    pub user_id: Uuid,
+    pub market: String,
+    pub maker_rate: BigDecimal,
+    pub taker_rate: BigDecimal,
+    pub created_at: DateTime<Utc>
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DbMarket {
+    pub market: String,
+    pub state: MarketState,
+    pub state_until: Option<DateTime<Utc>>,
+    pub max_slippage_pct: Option<BigDecimal>,
+    pub slippage_reference: SlippageReference,
+    pub price_band_pct: Option<BigDecimal>,
+    pub breaker_move_pct: Option<BigDecimal>,
+    pub breaker_window_secs: i32,
+    pub breaker_cooldown_secs: i32,
+    pub allow_cancels_when_halted: bool,
+    pub auction_duration_secs: i32,
+    pub matching_algorithm: MatchingAlgorithmKind,
+    pub lot_size: BigDecimal,
+    pub tick_size: BigDecimal,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DbApiKey {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub api_key: String,
+    pub encrypted_secret: String,
+    pub label: Option<String>,
+    pub scopes: Vec<String>,
+    pub allowed_ips: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct DbCandle {
+    pub bucket: DateTime<Utc>,
+    pub open: BigDecimal,
+    pub high: BigDecimal,
+    pub low: BigDecimal,
+    pub close: BigDecimal,
+    pub volume: BigDecimal,
+    pub quote_volume: BigDecimal,
+    pub trade_count: i64
+}
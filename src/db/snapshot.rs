@@ -0,0 +1,28 @@
+use sqlx::{Pool, Postgres};
+
+use crate::service::MarketState;
+
+pub async fn create_engine_snapshot(
+    pool: &Pool<Postgres>,
+    market: &str,
+    seq: i64,
+    state: MarketState,
+    orderbook: serde_json::Value,
+    balances: serde_json::Value
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO engine_snapshots (market, seq, state, orderbook, balances)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        market,
+        seq,
+        state as MarketState,
+        orderbook,
+        balances
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
@@ -1,23 +1,58 @@
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
 use sqlx::{Pool, Postgres};
+use uuid::Uuid;
 
-use crate::{db::schema::DbTrade, service::{InsertTradeArgs, Trade}};
+use crate::{db::schema::{DbFill, DbTrade}, service::{InsertTradeArgs, PublicTrade, Side, Trade}};
 
+//filters for a user's fills, None matches everything
+pub struct FillFilter {
+    pub market: Option<String>,
+    pub side: Option<Side>,
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    //(created_at, trade id, side) of the last fill of the previous page, side tells apart both fills of a self trade
+    pub before: Option<(DateTime<Utc>, Uuid, Side)>,
+    pub limit: i64
+}
 
 pub async fn create_trade(pool: &Pool<Postgres>, insert_trade_args: InsertTradeArgs) -> anyhow::Result<Trade> {
+    let created_at = DateTime::<Utc>::from_timestamp_millis(insert_trade_args.created_at).unwrap_or_else(Utc::now);
+
     let db_trade = sqlx::query_as!(
         DbTrade,
         r#"
         INSERT INTO trades (
+            id,
             buy_order_id,
             sell_order_id,
             price,
-            quantity
+            quantity,
+            buyer_fee,
+            seller_fee,
+            market,
+            buyer_user_id,
+            seller_user_id,
+            maker_order_id,
+            taker_side,
+            seq,
+            created_at
         )
         VALUES (
             $1,
             $2,
             $3,
-            $4
+            $4,
+            $5,
+            $6,
+            $7,
+            $8,
+            $9,
+            $10,
+            $11,
+            $12,
+            $13,
+            $14
         )
         RETURNING
             id,
@@ -25,12 +60,30 @@ pub async fn create_trade(pool: &Pool<Postgres>, insert_trade_args: InsertTradeA
             sell_order_id,
             price,
             quantity,
+            buyer_fee,
+            seller_fee,
+            market,
+            buyer_user_id AS "buyer_user_id!",
+            seller_user_id AS "seller_user_id!",
+            maker_order_id AS "maker_order_id!",
+            taker_side AS "taker_side!: Side",
+            seq,
             created_at
         "#,
+        insert_trade_args.id,
         insert_trade_args.buy_order_id,
         insert_trade_args.sell_order_id,
         insert_trade_args.price,
-        insert_trade_args.quantity
+        insert_trade_args.quantity,
+        insert_trade_args.buyer_fee,
+        insert_trade_args.seller_fee,
+        insert_trade_args.market,
+        insert_trade_args.buyer_user_id,
+        insert_trade_args.seller_user_id,
+        insert_trade_args.maker_order_id,
+        insert_trade_args.taker_side as Side,
+        insert_trade_args.seq,
+        created_at
     )
     .fetch_one(pool)
     .await?;
@@ -41,8 +94,246 @@ pub async fn create_trade(pool: &Pool<Postgres>, insert_trade_args: InsertTradeA
         sell_order_id: db_trade.sell_order_id,
         price: db_trade.price,
         quantity: db_trade.quantity,
+        buyer_fee: db_trade.buyer_fee,
+        seller_fee: db_trade.seller_fee,
+        market: db_trade.market.unwrap_or_default(),
+        buyer_user_id: db_trade.buyer_user_id,
+        seller_user_id: db_trade.seller_user_id,
+        maker_order_id: db_trade.maker_order_id,
+        taker_side: db_trade.taker_side,
+        seq: db_trade.seq.unwrap_or_default(),
         created_at: db_trade.created_at.timestamp_millis()
     };
 
     Ok(trade)
-}
\ No newline at end of file
+}
+
+pub async fn get_last_trade_seq(pool: &Pool<Postgres>, market: &str) -> anyhow::Result<i64> {
+    let seq = sqlx::query_scalar!(
+        r#"
+        SELECT COALESCE(MAX(seq), 0) AS "seq!"
+        FROM trades
+        WHERE market = $1
+        "#,
+        market
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(seq)
+}
+
+pub async fn get_last_trade_price(pool: &Pool<Postgres>, market: &str) -> anyhow::Result<Option<BigDecimal>> {
+    let price = sqlx::query_scalar!(
+        r#"
+        SELECT price
+        FROM trades
+        WHERE market = $1
+        ORDER BY seq DESC
+        LIMIT 1
+        "#,
+        market
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(price)
+}
+//one multi-row insert for a batch of trades
+pub async fn create_trades(pool: &Pool<Postgres>, insert_trade_args: &[InsertTradeArgs]) -> anyhow::Result<u64> {
+    let ids: Vec<Uuid> = insert_trade_args.iter().map(|args| args.id).collect();
+    let buy_order_ids: Vec<Uuid> = insert_trade_args.iter().map(|args| args.buy_order_id).collect();
+    let sell_order_ids: Vec<Uuid> = insert_trade_args.iter().map(|args| args.sell_order_id).collect();
+    let prices: Vec<BigDecimal> = insert_trade_args.iter().map(|args| args.price.clone()).collect();
+    let quantities: Vec<BigDecimal> = insert_trade_args.iter().map(|args| args.quantity.clone()).collect();
+    let buyer_fees: Vec<BigDecimal> = insert_trade_args.iter().map(|args| args.buyer_fee.clone()).collect();
+    let seller_fees: Vec<BigDecimal> = insert_trade_args.iter().map(|args| args.seller_fee.clone()).collect();
+    let markets: Vec<String> = insert_trade_args.iter().map(|args| args.market.clone()).collect();
+    let buyer_user_ids: Vec<Uuid> = insert_trade_args.iter().map(|args| args.buyer_user_id).collect();
+    let seller_user_ids: Vec<Uuid> = insert_trade_args.iter().map(|args| args.seller_user_id).collect();
+    let maker_order_ids: Vec<Uuid> = insert_trade_args.iter().map(|args| args.maker_order_id).collect();
+    let taker_sides: Vec<Side> = insert_trade_args.iter().map(|args| args.taker_side).collect();
+    let seqs: Vec<i64> = insert_trade_args.iter().map(|args| args.seq).collect();
+    let created_ats: Vec<DateTime<Utc>> = insert_trade_args.iter()
+        .map(|args| DateTime::<Utc>::from_timestamp_millis(args.created_at).unwrap_or_else(Utc::now))
+        .collect();
+
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO trades (
+            id,
+            buy_order_id,
+            sell_order_id,
+            price,
+            quantity,
+            buyer_fee,
+            seller_fee,
+            market,
+            buyer_user_id,
+            seller_user_id,
+            maker_order_id,
+            taker_side,
+            seq,
+            created_at
+        )
+        SELECT * FROM UNNEST(
+            $1::UUID[],
+            $2::UUID[],
+            $3::UUID[],
+            $4::NUMERIC[],
+            $5::NUMERIC[],
+            $6::NUMERIC[],
+            $7::NUMERIC[],
+            $8::VARCHAR[],
+            $9::UUID[],
+            $10::UUID[],
+            $11::UUID[],
+            $12::VARCHAR[],
+            $13::BIGINT[],
+            $14::TIMESTAMPTZ[]
+        )
+        "#,
+        &ids,
+        &buy_order_ids,
+        &sell_order_ids,
+        &prices,
+        &quantities,
+        &buyer_fees,
+        &seller_fees,
+        &markets,
+        &buyer_user_ids,
+        &seller_user_ids,
+        &maker_order_ids,
+        &taker_sides as &[Side],
+        &seqs,
+        &created_ats
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+//newest `limit` trades, returned oldest first
+pub async fn get_recent_trades(pool: &Pool<Postgres>, market: &str, limit: i64) -> anyhow::Result<Vec<PublicTrade>> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            price,
+            quantity,
+            taker_side AS "taker_side: Side",
+            seq AS "seq!",
+            created_at
+        FROM trades
+        WHERE market = $1 AND seq IS NOT NULL
+        ORDER BY seq DESC
+        LIMIT $2
+        "#,
+        market,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut trades: Vec<PublicTrade> = rows.into_iter()
+        .filter_map(|row| Some(PublicTrade {
+            market: market.to_string(),
+            seq: row.seq,
+            price: row.price,
+            quantity: row.quantity,
+            taker_side: row.taker_side?,
+            created_at: row.created_at.timestamp_millis()
+        }))
+        .collect();
+    trades.reverse();
+
+    Ok(trades)
+}
+
+//oldest first
+pub async fn get_trades_since(pool: &Pool<Postgres>, market: &str, since: DateTime<Utc>) -> anyhow::Result<Vec<PublicTrade>> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            price,
+            quantity,
+            taker_side AS "taker_side: Side",
+            seq AS "seq!",
+            created_at
+        FROM trades
+        WHERE market = $1 AND seq IS NOT NULL AND created_at >= $2
+        ORDER BY seq ASC
+        "#,
+        market,
+        since
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let trades: Vec<PublicTrade> = rows.into_iter()
+        .filter_map(|row| Some(PublicTrade {
+            market: market.to_string(),
+            seq: row.seq,
+            price: row.price,
+            quantity: row.quantity,
+            taker_side: row.taker_side?,
+            created_at: row.created_at.timestamp_millis()
+        }))
+        .collect();
+
+    Ok(trades)
+}
+
+//each trade is a fill for the buyer and one for the seller, newest first
+pub async fn get_user_fills(pool: &Pool<Postgres>, user_id: Uuid, filter: &FillFilter) -> anyhow::Result<Vec<DbFill>> {
+    let (before_created_at, before_id, before_side) = match filter.before {
+        Some((created_at, id, side)) => (Some(created_at), Some(id), Some(side)),
+        None => (None, None, None)
+    };
+
+    let db_fills = sqlx::query_as!(
+        DbFill,
+        r#"
+        SELECT
+            id AS "trade_id!",
+            order_id AS "order_id!",
+            market,
+            seq,
+            side AS "side!: Side",
+            price AS "price!",
+            quantity AS "quantity!",
+            fee AS "fee!",
+            maker_order_id = order_id AS "is_maker!",
+            created_at AS "created_at!"
+        FROM (
+            SELECT id, buy_order_id AS order_id, 'Bid'::VARCHAR AS side, price, quantity, buyer_fee AS fee, market, seq, maker_order_id, created_at
+            FROM trades
+            WHERE buyer_user_id = $1
+            UNION ALL
+            SELECT id, sell_order_id AS order_id, 'Ask'::VARCHAR AS side, price, quantity, seller_fee AS fee, market, seq, maker_order_id, created_at
+            FROM trades
+            WHERE seller_user_id = $1
+        ) fills
+        WHERE ($2::VARCHAR IS NULL OR market = $2)
+            AND ($3::VARCHAR IS NULL OR side = $3)
+            AND ($4::TIMESTAMPTZ IS NULL OR created_at >= $4)
+            AND ($5::TIMESTAMPTZ IS NULL OR created_at < $5)
+            AND ($6::TIMESTAMPTZ IS NULL OR (created_at, id, side) < ($6, $7::UUID, $8::VARCHAR))
+        ORDER BY created_at DESC, id DESC, side DESC
+        LIMIT $9
+        "#,
+        user_id,
+        filter.market,
+        filter.side as Option<Side>,
+        filter.start,
+        filter.end,
+        before_created_at,
+        before_id,
+        before_side as Option<Side>,
+        filter.limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(db_fills)
+}
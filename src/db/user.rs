@@ -1,8 +1,8 @@
-use sqlx::{Pool, Postgres};
+use sqlx::{PgExecutor, Pool, Postgres};
 
-use crate::db::schema::DbUser;
+use crate::{db::{create_user_balance, schema::DbUser}, service::UserBalance};
 
-pub async fn create_user(pool: &Pool<Postgres>, email: &str, password: &str) -> anyhow::Result<DbUser> {
+pub async fn create_user(executor: impl PgExecutor<'_>, email: &str, password_hash: &str) -> anyhow::Result<DbUser> {
     let user = sqlx::query_as!(
         DbUser,
         r#"
@@ -11,10 +11,38 @@ pub async fn create_user(pool: &Pool<Postgres>, email: &str, password: &str) ->
         RETURNING id, email, password_hash, created_at
         "#,
         email,
-        password
+        password_hash
     )
-    .fetch_one(pool)
+    .fetch_one(executor)
     .await?;
 
     Ok(user)
-}
\ No newline at end of file
+}
+
+//user and its empty balance row are created together so no account exists without a balance
+pub async fn create_account(pool: &Pool<Postgres>, email: &str, password_hash: &str) -> anyhow::Result<(DbUser, UserBalance)> {
+    let mut tx = pool.begin().await?;
+
+    let user = create_user(&mut *tx, email, password_hash).await?;
+    let user_balance = create_user_balance(&mut *tx, user.id).await?;
+
+    tx.commit().await?;
+
+    Ok((user, user_balance))
+}
+
+pub async fn get_user_by_email(pool: &Pool<Postgres>, email: &str) -> anyhow::Result<Option<DbUser>> {
+    let user = sqlx::query_as!(
+        DbUser,
+        r#"
+        SELECT id, email, password_hash, created_at
+        FROM users
+        WHERE email = $1
+        "#,
+        email
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(user)
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+//recompute every user's trailing 30 day quote volume and the fee tier it qualifies for
+pub async fn refresh_user_trading_volume(pool: &Pool<Postgres>, market: &str) -> anyhow::Result<u64> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM user_trading_volume
+        WHERE market = $1
+        "#,
+        market
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO user_trading_volume (
+            user_id,
+            market,
+            quote_volume,
+            tier
+        )
+        SELECT
+            v.user_id,
+            $1::VARCHAR,
+            v.quote_volume,
+            (
+                SELECT ft.tier
+                FROM fee_tiers ft
+                WHERE ft.market = $1
+                    AND ft.min_volume <= v.quote_volume
+                ORDER BY ft.min_volume DESC
+                LIMIT 1
+            )
+        FROM (
+            SELECT
+                p.user_id,
+                SUM(p.quote_volume) AS quote_volume
+            FROM (
+                SELECT buyer_user_id AS user_id, price * quantity AS quote_volume
+                FROM trades
+                WHERE market = $1 AND created_at >= NOW() - INTERVAL '30 days'
+                UNION ALL
+                -- a self trade counts once for its user
+                SELECT seller_user_id AS user_id, price * quantity AS quote_volume
+                FROM trades
+                WHERE market = $1 AND created_at >= NOW() - INTERVAL '30 days'
+                    AND seller_user_id IS DISTINCT FROM buyer_user_id
+            ) p
+            GROUP BY p.user_id
+        ) v
+        "#,
+        market
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn get_user_fee_tiers(pool: &Pool<Postgres>, market: &str) -> anyhow::Result<HashMap<Uuid, i32>> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            user_id,
+            tier AS "tier!"
+        FROM user_trading_volume
+        WHERE market = $1 AND tier IS NOT NULL
+        "#,
+        market
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut user_tiers: HashMap<Uuid, i32> = HashMap::new();
+    for row in rows.iter() {
+        user_tiers.insert(row.user_id, row.tier);
+    }
+
+    Ok(user_tiers)
+}
@@ -1,18 +1,33 @@
-use actix_web::{App, HttpServer, web};
+use std::{env, sync::{Arc, Mutex}, time::Duration};
+
+use actix_web::{App, HttpServer, middleware::from_fn, web};
 use sqlx::{Pool, Postgres};
-use tokio::sync::mpsc::{self, Sender};
+use tokio::{signal::unix::{SignalKind, signal}, sync::{broadcast, mpsc, watch}};
 
-use crate::{db::init_db, routes::signup, service::{BalanceEvent, BalanceWorker, Engine, EngineIx, OrderEvent, OrderWorker, TradeEvent, TradeWorker}};
+use crate::{auth::{ReplayCache, verify_api_key}, db::init_db, routes::{cancel_order, get_auction, create_order, get_balances, get_fills, get_ledger, get_order, get_order_history, get_orders, create_user_api_key, get_depth, get_klines, get_ticker, get_trades, list_user_api_keys, login, market_ws, metrics, private_ws, reconcile_market, revoke_user_api_key, set_market_state, signup}, service::{BalanceEvent, BalanceWorker, Engine, EngineIx, EngineSender, MarketData, MarketEvent, MarketSnapshot, MARKET_EVENT_CAPACITY, UserFeed, UserFeedSender, engine_channel, OrderEvent, OrderWorker, ReconcileRequest, ReconcileWorker, Supervisor, TradeEvent, TradeWorker, VolumeWorker}};
 
+pub mod auth;
 pub mod db;
 pub mod routes;
 pub mod service;
 
+const DEFAULT_MARKET: &str = "BTC_USDC";
+
+//instructions each engine lane holds before clients get 503s, override with ENGINE_QUEUE_DEPTH
+const DEFAULT_ENGINE_QUEUE_DEPTH: usize = 100;
+
+//how long workers get to flush their queues to postgres once the engine has stopped
+const WORKER_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
 
 #[derive(Clone)]
 pub struct AppData {
     pub pool: Pool<Postgres>,
-    pub engine_tx: Sender<EngineIx>
+    pub engine_tx: EngineSender,
+    pub reconcile_tx: mpsc::Sender<ReconcileRequest>,
+    pub replay_cache: Arc<Mutex<ReplayCache>>,
+    pub market_data: watch::Receiver<MarketSnapshot>,
+    pub market_events: broadcast::Sender<MarketEvent>,
+    pub user_feed: UserFeed
 }
 
 
@@ -20,50 +35,171 @@ pub struct AppData {
 async fn main() -> anyhow::Result<()> {
     
     let db = init_db().await?;
+
+    let market = env::var("MARKET").unwrap_or(DEFAULT_MARKET.to_string());
     
     let balance_db = db.clone();
     let trade_db = db.clone();
     let order_db = db.clone();
-    let engine_db = db.clone();
+    //the engine runs on its own runtime and connections opened there die with it,
+    //so it gets a pool of its own instead of leaving dead connections in the shared one
+    let engine_db = init_db().await?;
+    let volume_db = db.clone();
+    let reconcile_db = db.clone();
 
     let (balance_tx, balance_rx) = mpsc::channel::<BalanceEvent>(100);
     let (trade_tx, trade_rx) = mpsc::channel::<TradeEvent>(100);
     let (order_tx, order_rx) = mpsc::channel::<OrderEvent>(100);
-    let (engine_tx, engine_rx) = mpsc::channel::<EngineIx>(100);
+    let engine_queue_depth = match env::var("ENGINE_QUEUE_DEPTH") {
+        Ok(depth) => depth.parse::<usize>()?,
+        Err(_) => DEFAULT_ENGINE_QUEUE_DEPTH
+    };
+    let (engine_tx, engine_rx) = engine_channel(engine_queue_depth);
+    let (reconcile_tx, reconcile_rx) = mpsc::channel::<ReconcileRequest>(10);
+    let (snapshot_tx, snapshot_rx) = watch::channel(MarketSnapshot::default(&market));
+    let (market_event_tx, _) = broadcast::channel::<MarketEvent>(MARKET_EVENT_CAPACITY);
+    //order by order feed for full book reconstruction, off unless L3_FEED=true
+    let l3_enabled = match env::var("L3_FEED") {
+        Ok(flag) => flag.parse::<bool>()?,
+        Err(_) => false
+    };
+    let market_data = MarketData::default(snapshot_tx, market_event_tx.clone(), l3_enabled);
+    let user_feed = UserFeed::default();
     
-    tokio::spawn(async move {
-        let mut balance_worker = BalanceWorker::default(balance_db, balance_rx);
-        balance_worker.run();
+    let balance_worker = tokio::spawn(async move {
+        let mut supervisor = Supervisor::default(balance_db.clone(), balance_rx, move || BalanceWorker::default(balance_db.clone()));
+        supervisor.run().await;
     });
 
-    tokio::spawn(async move {
-        let mut trade_worker = TradeWorker::default(trade_db, trade_rx);
-        trade_worker.run();
+    let trade_worker = tokio::spawn(async move {
+        let mut supervisor = Supervisor::default(trade_db.clone(), trade_rx, move || TradeWorker::default(trade_db.clone()));
+        supervisor.run().await;
     });
 
-    tokio::spawn(async move {
-        let mut order_worker = OrderWorker::default(order_db, order_rx);
-        order_worker.run();
+    let order_worker = tokio::spawn(async move {
+        let mut supervisor = Supervisor::default(order_db.clone(), order_rx, move || OrderWorker::default(order_db.clone()));
+        supervisor.run().await;
+    });
+
+    let volume_market = market.clone();
+    let volume_engine_tx = engine_tx.clone();
+    let volume_worker = tokio::spawn(async move {
+        let mut volume_worker = VolumeWorker::default(volume_db, volume_market, volume_engine_tx);
+        volume_worker.run().await;
     });
 
-    std::thread::spawn(move || {
-        let mut engine = Engine::default(balance_tx, trade_tx, order_tx, engine_db, engine_rx);
-        engine.run();
+    let reconcile_worker_market = market.clone();
+    let reconcile_engine_tx = engine_tx.clone();
+    let reconcile_worker = tokio::spawn(async move {
+        let mut reconcile_worker = ReconcileWorker::default(reconcile_db, reconcile_worker_market, reconcile_engine_tx, reconcile_rx);
+        reconcile_worker.run().await;
     });
+
+    let engine_user_feed = user_feed.clone();
+    let engine = std::thread::spawn(move || {
+        let balance_tx = UserFeedSender::default(balance_tx, engine_user_feed.clone());
+        let trade_tx = UserFeedSender::default(trade_tx, engine_user_feed.clone());
+        let order_tx = UserFeedSender::default(order_tx, engine_user_feed);
+        let mut engine = Engine::default(market, balance_tx, trade_tx, order_tx, engine_db, engine_rx, market_data);
+        engine.run()
+    });
+
+    let shutdown_tx = engine_tx.clone();
     
     let app_data  = AppData {
         pool: db.clone(),
-        engine_tx: engine_tx
+        engine_tx: engine_tx,
+        reconcile_tx: reconcile_tx,
+        replay_cache: Arc::new(Mutex::new(ReplayCache::default())),
+        market_data: snapshot_rx,
+        market_events: market_event_tx,
+        user_feed: user_feed
     };
 
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         App::new()
             .app_data(web::Data::new(app_data.clone()))
+            .wrap(from_fn(verify_api_key))
             .service(signup)
+            .service(login)
+            .service(create_order)
+            .service(cancel_order)
+            //before /orders/{order_id} so "history" is not taken for an id
+            .service(get_order_history)
+            .service(get_orders)
+            .service(get_order)
+            .service(get_fills)
+            .service(get_balances)
+            .service(get_ledger)
+            .service(create_user_api_key)
+            .service(list_user_api_keys)
+            .service(revoke_user_api_key)
+            .service(set_market_state)
+            .service(reconcile_market)
+            .service(metrics)
+            .service(get_depth)
+            .service(get_trades)
+            .service(get_ticker)
+            .service(get_auction)
+            .service(get_klines)
+            .service(market_ws)
+            .service(private_ws)
     })
     .bind(("127.0.0.1", 8080))?
-    .run()
-    .await?;
+    .disable_signals()
+    .run();
 
+    //stop accepting requests on SIGINT/SIGTERM, in-flight requests are allowed to finish
+    let server_handle = server.handle();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        println!("Shutdown signal received, stopping HTTP server");
+        server_handle.stop(true).await;
+    });
+
+    server.await?;
+
+    //instructions already queued are processed before the shutdown instruction
+    if shutdown_tx.send(EngineIx::Shutdown).await.is_err() {
+        eprintln!("Engine already stopped");
+    }
+    drop(shutdown_tx);
+    volume_worker.abort();
+    reconcile_worker.abort();
+
+    let engine_result = match tokio::task::spawn_blocking(move || engine.join()).await? {
+        Ok(result) => result,
+        Err(_) => Err(anyhow::anyhow!("Engine thread panicked"))
+    };
+
+    //the engine dropped its event senders, workers return once their channels are drained
+    let drained = tokio::time::timeout(WORKER_DRAIN_TIMEOUT, async {
+        let _ = balance_worker.await;
+        let _ = trade_worker.await;
+        let _ = order_worker.await;
+    }).await;
+
+    engine_result?;
+    if drained.is_err() {
+        return Err(anyhow::anyhow!("Workers did not drain within {:?}", WORKER_DRAIN_TIMEOUT));
+    }
+
+    println!("Shutdown complete");
     Ok(())
 }
+
+async fn shutdown_signal() {
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            eprintln!("Failed to listen for SIGTERM: {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        }
+    };
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
@@ -0,0 +1,66 @@
+use std::env;
+
+use actix_web::{HttpRequest, HttpResponse, post, web};
+use subtle::ConstantTimeEq;
+use tokio::sync::oneshot;
+
+use crate::{AppData, routes::types::SetMarketState, service::{EngineIx, ReconcileRequest}};
+
+//admin endpoints require the ADMIN_TOKEN from the environment in the x-admin-token header
+pub fn is_admin(req: &HttpRequest) -> bool {
+    let Ok(admin_token) = env::var("ADMIN_TOKEN") else {
+        return false;
+    };
+
+    match req.headers().get("x-admin-token") {
+        //constant time, so the token can not be guessed byte by byte from response times
+        Some(token) => {
+            !admin_token.is_empty() && bool::from(token.as_bytes().ct_eq(admin_token.as_bytes()))
+        }
+        None => {
+            false
+        }
+    }
+}
+
+//move a market through its lifecycle, the engine rejects transitions that are not allowed
+#[post("/admin/markets/{market}/state")]
+pub async fn set_market_state(req: HttpRequest, data: web::Data<AppData>, path: web::Path<String>, body: web::Json<SetMarketState>) -> HttpResponse {
+    if !is_admin(&req) {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    let ix = EngineIx::SetMarketState {
+        market: path.into_inner(),
+        state: body.into_inner().state
+    };
+
+    match data.engine_tx.send(ix).await {
+        Ok(_) => HttpResponse::Accepted().finish(),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+//check the engine's balances and resting orders against postgres and return the drift found
+#[post("/admin/markets/{market}/reconcile")]
+pub async fn reconcile_market(req: HttpRequest, data: web::Data<AppData>, path: web::Path<String>) -> HttpResponse {
+    if !is_admin(&req) {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    let (reply_tx, reply_rx) = oneshot::channel();
+    let request = ReconcileRequest {
+        market: path.into_inner(),
+        reply: reply_tx
+    };
+
+    if let Err(e) = data.reconcile_tx.send(request).await {
+        return HttpResponse::InternalServerError().body(e.to_string());
+    }
+
+    //the worker drops the reply for unknown markets or failed runs
+    match reply_rx.await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(_) => HttpResponse::InternalServerError().body("Reconciliation failed")
+    }
+}
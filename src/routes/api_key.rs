@@ -0,0 +1,56 @@
+use actix_web::{HttpResponse, delete, get, post, web};
+use uuid::Uuid;
+
+use crate::{AppData, auth::{AuthUser, encrypt_secret, generate_api_key}, db::{create_api_key, get_user_api_keys, revoke_api_key}, routes::types::{ApiKeyResponse, CreateApiKey, CreateApiKeyResponse}};
+
+//keys can only be managed from a logged in session, never with another api key
+
+#[post("/api-keys")]
+pub async fn create_user_api_key(auth: AuthUser, data: web::Data<AppData>, body: web::Json<CreateApiKey>) -> HttpResponse {
+    if auth.api_key_id.is_some() {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    let body = body.into_inner();
+    if body.scopes.is_empty() {
+        return HttpResponse::BadRequest().body("At least one scope is required");
+    }
+
+    let (api_key, secret) = generate_api_key();
+    let encrypted_secret = match encrypt_secret(&secret) {
+        Ok(encrypted_secret) => encrypted_secret,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+    let scopes: Vec<String> = body.scopes.iter().map(|scope| scope.as_str().to_string()).collect();
+    let allowed_ips: Vec<String> = body.allowed_ips.iter().map(|ip| ip.to_string()).collect();
+
+    match create_api_key(&data.pool, auth.user_id, &api_key, &encrypted_secret, body.label.as_deref(), &scopes, &allowed_ips).await {
+        Ok(db_api_key) => HttpResponse::Ok().json(CreateApiKeyResponse::new(db_api_key, secret)),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+#[get("/api-keys")]
+pub async fn list_user_api_keys(auth: AuthUser, data: web::Data<AppData>) -> HttpResponse {
+    if auth.api_key_id.is_some() {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    match get_user_api_keys(&data.pool, auth.user_id).await {
+        Ok(db_api_keys) => HttpResponse::Ok().json(db_api_keys.into_iter().map(ApiKeyResponse::from).collect::<Vec<_>>()),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+#[delete("/api-keys/{id}")]
+pub async fn revoke_user_api_key(auth: AuthUser, data: web::Data<AppData>, path: web::Path<Uuid>) -> HttpResponse {
+    if auth.api_key_id.is_some() {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    match revoke_api_key(&data.pool, auth.user_id, path.into_inner()).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
@@ -0,0 +1,87 @@
+use actix_web::{HttpResponse, get, web};
+use tokio::sync::oneshot;
+
+use crate::{AppData, auth::{ApiKeyScope, AuthUser}, db::{LedgerFilter, get_balance_ledger}, routes::{engine_unavailable, time_range, types::{BalancesResponse, LedgerEntryResponse, LedgerQuery, Page}}, service::{Asset, EngineIx, UserBalance}};
+
+const DEFAULT_LEDGER_LIMIT: i64 = 100;
+const MAX_LEDGER_LIMIT: i64 = 500;
+
+//read from the engine, user_balance can lag it by what the balance worker has not written yet
+#[get("/balances")]
+pub async fn get_balances(auth: AuthUser, data: web::Data<AppData>) -> HttpResponse {
+    if !auth.has_scope(ApiKeyScope::Read) {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    let (reply_tx, reply_rx) = oneshot::channel::<UserBalance>();
+    let ix = EngineIx::GetBalance {
+        user_id: auth.user_id,
+        reply: reply_tx
+    };
+
+    if let Err(e) = data.engine_tx.try_send(ix) {
+        return engine_unavailable(e);
+    }
+
+    //the engine drops the reply for users it does not know
+    let user_balance = match reply_rx.await {
+        Ok(user_balance) => user_balance,
+        Err(_) => return HttpResponse::NotFound().finish()
+    };
+
+    let market = data.market_data.borrow().market.clone();
+    HttpResponse::Ok().json(BalancesResponse::new(&market, &user_balance))
+}
+
+//every change to one asset's balance, newest first. written by the balance worker, so it can lag GET /balances
+#[get("/balances/{asset}/ledger")]
+pub async fn get_ledger(auth: AuthUser, data: web::Data<AppData>, path: web::Path<String>, query: web::Query<LedgerQuery>) -> HttpResponse {
+    if !auth.has_scope(ApiKeyScope::Read) {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    let market = data.market_data.borrow().market.clone();
+    let Some(asset) = Asset::from_symbol(&market, &path.into_inner()) else {
+        return HttpResponse::NotFound().body("Unknown asset");
+    };
+
+    let query = query.into_inner();
+    let Some((start, end)) = time_range(query.start, query.end) else {
+        return HttpResponse::BadRequest().body("Invalid start or end");
+    };
+
+    let before = match query.cursor {
+        Some(cursor) => match cursor.parse::<i64>() {
+            Ok(before) => Some(before),
+            Err(_) => return HttpResponse::BadRequest().body("Invalid cursor")
+        },
+        None => None
+    };
+
+    let filter = LedgerFilter {
+        start: start,
+        end: end,
+        before: before,
+        limit: query.limit.unwrap_or(DEFAULT_LEDGER_LIMIT).clamp(1, MAX_LEDGER_LIMIT)
+    };
+
+    let db_entries = match get_balance_ledger(&data.pool, auth.user_id, asset, &filter).await {
+        Ok(db_entries) => db_entries,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    //a full page may be followed by more
+    let next_cursor = match db_entries.last() {
+        Some(last) if db_entries.len() as i64 == filter.limit => {
+            Some(last.id.to_string())
+        }
+        _ => {
+            None
+        }
+    };
+
+    HttpResponse::Ok().json(Page {
+        items: db_entries.into_iter().map(|db_entry| LedgerEntryResponse::new(&market, db_entry)).collect(),
+        next_cursor: next_cursor
+    })
+}
@@ -0,0 +1,93 @@
+use actix_web::{HttpResponse, get, web};
+use chrono::{DateTime, Utc};
+
+use crate::{AppData, db::get_candles, routes::types::{DepthQuery, KlinesQuery, MarketQuery, TradesQuery}, service::{RECENT_TRADES, SNAPSHOT_DEPTH}};
+
+const DEFAULT_DEPTH_LIMIT: usize = 100;
+const DEFAULT_TRADES_LIMIT: usize = 50;
+const DEFAULT_KLINES_LIMIT: i64 = 500;
+const MAX_KLINES_LIMIT: i64 = 1000;
+
+//all market data is read from the snapshot the engine publishes, never from the engine itself
+
+#[get("/depth")]
+pub async fn get_depth(data: web::Data<AppData>, query: web::Query<DepthQuery>) -> HttpResponse {
+    let snapshot = data.market_data.borrow();
+    if snapshot.market != query.market {
+        return HttpResponse::NotFound().body("Unknown market");
+    }
+
+    //seq is the book sequence, see service::ws for keeping a local book in sync
+    let limit = query.limit.unwrap_or(DEFAULT_DEPTH_LIMIT).min(SNAPSHOT_DEPTH);
+    HttpResponse::Ok().json(snapshot.depth(limit))
+}
+
+#[get("/trades")]
+pub async fn get_trades(data: web::Data<AppData>, query: web::Query<TradesQuery>) -> HttpResponse {
+    let snapshot = data.market_data.borrow();
+    if snapshot.market != query.market {
+        return HttpResponse::NotFound().body("Unknown market");
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_TRADES_LIMIT).min(RECENT_TRADES);
+    let trades: Vec<_> = snapshot.recent_trades.iter().take(limit).cloned().collect();
+
+    HttpResponse::Ok().json(trades)
+}
+
+#[get("/ticker")]
+pub async fn get_ticker(data: web::Data<AppData>, query: web::Query<MarketQuery>) -> HttpResponse {
+    let snapshot = data.market_data.borrow();
+    if snapshot.market != query.market {
+        return HttpResponse::NotFound().body("Unknown market");
+    }
+
+    HttpResponse::Ok().json(&snapshot.ticker)
+}
+
+//indicative uncrossing price and volume while the market is in its call auction
+#[get("/auction")]
+pub async fn get_auction(data: web::Data<AppData>, query: web::Query<MarketQuery>) -> HttpResponse {
+    let snapshot = data.market_data.borrow();
+    if snapshot.market != query.market {
+        return HttpResponse::NotFound().body("Unknown market");
+    }
+
+    match &snapshot.auction {
+        Some(auction) => HttpResponse::Ok().json(auction),
+        None => HttpResponse::NotFound().body("No auction is running")
+    }
+}
+
+//candles come from the continuous aggregates, the newest one includes trades not yet materialized
+#[get("/klines")]
+pub async fn get_klines(data: web::Data<AppData>, query: web::Query<KlinesQuery>) -> HttpResponse {
+    if data.market_data.borrow().market != query.market {
+        return HttpResponse::NotFound().body("Unknown market");
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_KLINES_LIMIT).clamp(1, MAX_KLINES_LIMIT);
+
+    //without a start, return the `limit` candles leading up to end.
+    //start is moved back to its bucket so the candle containing it is returned
+    let end = query.end.unwrap_or(Utc::now().timestamp_millis());
+    let start = match query.start {
+        Some(start) => query.interval.open_time(start),
+        None => query.interval.open_time(end) - (limit - 1) * query.interval.millis()
+    };
+    if start > end {
+        return HttpResponse::BadRequest().body("start must not be after end");
+    }
+
+    let (Some(start), Some(end)) = (DateTime::<Utc>::from_timestamp_millis(start), DateTime::<Utc>::from_timestamp_millis(end)) else {
+        return HttpResponse::BadRequest().body("Invalid start or end");
+    };
+
+    //end is inclusive for callers, so the candle containing it is returned
+    let end = end + chrono::Duration::milliseconds(1);
+
+    match get_candles(&data.pool, &query.market, query.interval, start, end, limit).await {
+        Ok(candles) => HttpResponse::Ok().json(candles),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
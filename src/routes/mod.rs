@@ -1,14 +1,117 @@
-use actix_web::{HttpResponse, web, get};
-use crate::{AppData, db::create_user};
+use actix_web::{HttpResponse, get, web, post};
+use crate::{AppData, auth::{hash_password, issue_token, verify_login}, db::{create_account, get_user_by_email}, service::{EngineIx, EngineQueueError, Lane}};
 
 pub mod types;
 pub use types::*;
 
+pub mod admin;
+pub use admin::*;
 
-#[get("/signup")]
+pub mod order;
+pub use order::*;
+
+pub mod balance;
+pub use balance::*;
+
+pub mod api_key;
+pub use api_key::*;
+
+pub mod market_data;
+pub use market_data::*;
+
+pub mod ws;
+pub use ws::*;
+
+//seconds clients are told to wait when the engine queue is full
+const ENGINE_BUSY_RETRY_AFTER_SECS: u64 = 1;
+
+pub fn engine_unavailable(e: EngineQueueError) -> HttpResponse {
+    match e {
+        EngineQueueError::Busy => {
+            HttpResponse::ServiceUnavailable()
+                .insert_header(("Retry-After", ENGINE_BUSY_RETRY_AFTER_SECS.to_string()))
+                .body(e.to_string())
+        }
+        EngineQueueError::Closed => {
+            HttpResponse::ServiceUnavailable().body(e.to_string())
+        }
+    }
+}
+
+//prometheus text format
+#[get("/metrics")]
+pub async fn metrics(data: web::Data<AppData>) -> HttpResponse {
+    let mut body = String::new();
+
+    body.push_str("# TYPE engine_queue_depth gauge\n");
+    for lane in Lane::all() {
+        body.push_str(&format!("engine_queue_depth{{lane=\"{}\"}} {}\n", lane.as_str(), data.engine_tx.depth(lane)));
+    }
+
+    body.push_str("# TYPE engine_queue_max_depth gauge\n");
+    for lane in Lane::all() {
+        body.push_str(&format!("engine_queue_max_depth{{lane=\"{}\"}} {}\n", lane.as_str(), data.engine_tx.max_depth(lane)));
+    }
+
+    body.push_str("# TYPE engine_queue_rejected_total counter\n");
+    for lane in Lane::all() {
+        body.push_str(&format!("engine_queue_rejected_total{{lane=\"{}\"}} {}\n", lane.as_str(), data.engine_tx.rejected(lane)));
+    }
+
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body)
+}
+
+#[post("/signup")]
 pub async fn signup(data: web::Data<AppData>, body: web::Json<SignUp>) -> HttpResponse {
-    match create_user(&data.pool.clone(), &body.email, &body.password).await {
-        Ok(user) => HttpResponse::Ok().json(user),
+    //argon2 is slow on purpose, it runs on the blocking pool instead of holding up a worker
+    let password = body.password.clone();
+    let password_hash = match web::block(move || hash_password(&password)).await {
+        Ok(Ok(password_hash)) => password_hash,
+        Ok(Err(e)) => return HttpResponse::InternalServerError().body(e.to_string()),
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    let (user, user_balance) = match create_account(&data.pool.clone(), &body.email, &password_hash).await {
+        Ok(account) => account,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    //make the account usable without an engine restart. the account already exists, so a failed
+    //send is not an error for the caller, the engine picks the user up on its next reconciliation
+    if let Err(e) = data.engine_tx.send(EngineIx::RegisterUser(user_balance)).await {
+        eprintln!("Failed to register user {} with the engine: {}", user.id, e);
+    }
+
+    match issue_token(user.id) {
+        Ok(token) => HttpResponse::Ok().json(AuthResponse::new(user, token)),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+#[post("/login")]
+pub async fn login(data: web::Data<AppData>, body: web::Json<Login>) -> HttpResponse {
+    let user = match get_user_by_email(&data.pool.clone(), &body.email).await {
+        Ok(user) => user,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    //same response, and the same hashing work, for unknown email and wrong password
+    let password = body.password.clone();
+    let password_hash = user.as_ref().map(|user| user.password_hash.clone());
+    let verified = match web::block(move || verify_login(&password, password_hash.as_deref())).await {
+        Ok(verified) => verified,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    let user = match user {
+        Some(user) if verified => user,
+        _ => return HttpResponse::Unauthorized().body("Invalid email or password")
+    };
+
+    match issue_token(user.id) {
+        Ok(token) => HttpResponse::Ok().json(AuthResponse::new(user, token)),
         Err(e) => HttpResponse::InternalServerError().body(e.to_string())
     }
-}
\ No newline at end of file
+}
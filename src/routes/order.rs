@@ -0,0 +1,264 @@
+use actix_web::{HttpResponse, delete, get, post, web};
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::{AppData, auth::{ApiKeyScope, AuthUser}, db::{FillFilter, OrderFilter, get_user_fills, get_user_order, get_user_orders}, routes::{engine_unavailable, types::{CreateOrder, CreateOrderResponse, FillResponse, FillsQuery, OrdersQuery, Page}}, service::{CancelOrderArgs, CreateOrderArgs, EngineIx, OrderType, Side, Status}};
+
+const DEFAULT_PAGE_LIMIT: i64 = 100;
+const MAX_PAGE_LIMIT: i64 = 500;
+
+#[post("/order")]
+pub async fn create_order(auth: AuthUser, data: web::Data<AppData>, body: web::Json<CreateOrder>) -> HttpResponse {
+    if !auth.has_scope(ApiKeyScope::Trade) {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    let body = body.into_inner();
+    let order_type = body.order_type;
+    let zero = BigDecimal::from(0);
+
+    //sizes must be positive, the engine checks them against the market's tick and lot size.
+    //limit orders lock price * base_qty, so a quote_qty sent with one is ignored
+    let (limit_price, quote_qty) = match order_type {
+        OrderType::Limit => {
+            if body.limit_price <= zero || body.base_qty <= zero {
+                return HttpResponse::BadRequest().body("Limit orders need a positive limit_price and base_qty");
+            }
+            (body.limit_price, zero.clone())
+        }
+        OrderType::Market => {
+            if body.base_qty < zero || body.quote_qty < zero {
+                return HttpResponse::BadRequest().body("Quantities can not be negative");
+            }
+
+            //market sells are sized in base, market buys in base or in quote to spend
+            let sized = match body.side {
+                Side::Ask => body.base_qty > zero && body.quote_qty == zero,
+                Side::Bid => (body.base_qty > zero) != (body.quote_qty > zero)
+            };
+            if !sized {
+                return HttpResponse::BadRequest().body("Market sells need a base_qty, market buys either a base_qty or a quote_qty");
+            }
+            (zero.clone(), body.quote_qty)
+        }
+    };
+
+    let order_id = Uuid::new_v4();
+    let args = CreateOrderArgs {
+        order_id: order_id,
+        order_type: body.order_type,
+        side: body.side,
+        user_id: auth.user_id,
+        limit_price: limit_price,
+        base_qty: body.base_qty,
+        quote_qty: quote_qty
+    };
+
+    let ix = match order_type {
+        OrderType::Limit => EngineIx::CreateLimitOrder(args),
+        OrderType::Market => EngineIx::CreateMarketOrder(args)
+    };
+
+    match data.engine_tx.try_send(ix) {
+        Ok(_) => HttpResponse::Accepted().json(CreateOrderResponse { order_id: order_id }),
+        Err(e) => engine_unavailable(e)
+    }
+}
+
+//only the order's owner can cancel it, the engine checks the user against the resting order
+#[delete("/order/{order_id}")]
+pub async fn cancel_order(auth: AuthUser, data: web::Data<AppData>, path: web::Path<Uuid>) -> HttpResponse {
+    if !auth.has_scope(ApiKeyScope::Trade) {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    let args = CancelOrderArgs {
+        user_id: auth.user_id,
+        order_id: path.into_inner()
+    };
+
+    //the workers already recorded it as closed, there is nothing left to cancel.
+    //orders not written yet may still be queued in the engine, which answers for those
+    match get_user_order(&data.pool, args.user_id, args.order_id).await {
+        Ok(Some(db_order)) if db_order.status != Status::Open => {
+            return HttpResponse::Conflict().body(format!("Order is {:?}", db_order.status));
+        }
+        Ok(_) => {}
+        Err(e) => {
+            return HttpResponse::InternalServerError().body(e.to_string());
+        }
+    }
+
+    match data.engine_tx.try_send(EngineIx::CancelOrder(args)) {
+        Ok(_) => HttpResponse::Accepted().finish(),
+        Err(e) => engine_unavailable(e)
+    }
+}
+
+//orders and fills are read from postgres, so they can lag the engine by what the workers have not written yet
+
+#[get("/orders/history")]
+pub async fn get_order_history(auth: AuthUser, data: web::Data<AppData>, query: web::Query<OrdersQuery>) -> HttpResponse {
+    list_orders(auth, data, query.into_inner(), None).await
+}
+
+//open orders unless another status is asked for
+#[get("/orders")]
+pub async fn get_orders(auth: AuthUser, data: web::Data<AppData>, query: web::Query<OrdersQuery>) -> HttpResponse {
+    list_orders(auth, data, query.into_inner(), Some(Status::Open)).await
+}
+
+//orders of other users are not found rather than forbidden, so ids can not be probed
+#[get("/orders/{order_id}")]
+pub async fn get_order(auth: AuthUser, data: web::Data<AppData>, path: web::Path<Uuid>) -> HttpResponse {
+    if !auth.has_scope(ApiKeyScope::Read) {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    match get_user_order(&data.pool, auth.user_id, path.into_inner()).await {
+        Ok(Some(db_order)) => HttpResponse::Ok().json(db_order),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string())
+    }
+}
+
+#[get("/fills")]
+pub async fn get_fills(auth: AuthUser, data: web::Data<AppData>, query: web::Query<FillsQuery>) -> HttpResponse {
+    if !auth.has_scope(ApiKeyScope::Read) {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    let query = query.into_inner();
+    let Some((start, end)) = time_range(query.start, query.end) else {
+        return HttpResponse::BadRequest().body("Invalid start or end");
+    };
+
+    let before = match query.cursor {
+        Some(cursor) => match parse_fill_cursor(&cursor) {
+            Some(before) => Some(before),
+            None => return HttpResponse::BadRequest().body("Invalid cursor")
+        },
+        None => None
+    };
+
+    let filter = FillFilter {
+        market: query.market,
+        side: query.side,
+        start: start,
+        end: end,
+        before: before,
+        limit: query.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT)
+    };
+
+    let db_fills = match get_user_fills(&data.pool, auth.user_id, &filter).await {
+        Ok(db_fills) => db_fills,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    //a full page may be followed by more
+    let next_cursor = match db_fills.last() {
+        Some(last) if db_fills.len() as i64 == filter.limit => {
+            Some(format!("{}_{}_{}", last.created_at.timestamp_micros(), last.trade_id, side_str(last.side)))
+        }
+        _ => {
+            None
+        }
+    };
+
+    HttpResponse::Ok().json(Page {
+        items: db_fills.into_iter().map(FillResponse::from).collect(),
+        next_cursor: next_cursor
+    })
+}
+
+async fn list_orders(auth: AuthUser, data: web::Data<AppData>, query: OrdersQuery, default_status: Option<Status>) -> HttpResponse {
+    if !auth.has_scope(ApiKeyScope::Read) {
+        return HttpResponse::Forbidden().finish();
+    }
+
+    let Some((start, end)) = time_range(query.start, query.end) else {
+        return HttpResponse::BadRequest().body("Invalid start or end");
+    };
+
+    let before = match query.cursor {
+        Some(cursor) => match parse_order_cursor(&cursor) {
+            Some(before) => Some(before),
+            None => return HttpResponse::BadRequest().body("Invalid cursor")
+        },
+        None => None
+    };
+
+    let filter = OrderFilter {
+        market: query.market,
+        side: query.side,
+        status: query.status.or(default_status),
+        start: start,
+        end: end,
+        before: before,
+        limit: query.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT)
+    };
+
+    let db_orders = match get_user_orders(&data.pool, auth.user_id, &filter).await {
+        Ok(db_orders) => db_orders,
+        Err(e) => return HttpResponse::InternalServerError().body(e.to_string())
+    };
+
+    let next_cursor = match db_orders.last() {
+        Some(last) if db_orders.len() as i64 == filter.limit => {
+            Some(format!("{}_{}", last.created_at.timestamp_micros(), last.id))
+        }
+        _ => {
+            None
+        }
+    };
+
+    HttpResponse::Ok().json(Page {
+        items: db_orders,
+        next_cursor: next_cursor
+    })
+}
+
+//unix milliseconds to timestamps, None if either is out of range
+pub fn time_range(start: Option<i64>, end: Option<i64>) -> Option<(Option<DateTime<Utc>>, Option<DateTime<Utc>>)> {
+    let start = match start {
+        Some(start) => Some(DateTime::<Utc>::from_timestamp_millis(start)?),
+        None => None
+    };
+    let end = match end {
+        Some(end) => Some(DateTime::<Utc>::from_timestamp_millis(end)?),
+        None => None
+    };
+
+    Some((start, end))
+}
+
+//cursors are microseconds because postgres keeps created_at in microseconds,
+//anything coarser would skip rows created in the same millisecond
+fn parse_order_cursor(cursor: &str) -> Option<(DateTime<Utc>, Uuid)> {
+    let (created_at, id) = cursor.split_once('_')?;
+    let created_at = DateTime::<Utc>::from_timestamp_micros(created_at.parse().ok()?)?;
+    let id = Uuid::parse_str(id).ok()?;
+
+    Some((created_at, id))
+}
+
+fn parse_fill_cursor(cursor: &str) -> Option<(DateTime<Utc>, Uuid, Side)> {
+    let (created_at_id, side) = cursor.rsplit_once('_')?;
+    let (created_at, id) = parse_order_cursor(created_at_id)?;
+    let side = match side {
+        "Bid" => Side::Bid,
+        "Ask" => Side::Ask,
+        _ => return None
+    };
+
+    Some((created_at, id, side))
+}
+
+//as stored in postgres, fills sort on it
+fn side_str(side: Side) -> &'static str {
+    match side {
+        Side::Bid => "Bid",
+        Side::Ask => "Ask"
+    }
+}
@@ -1,7 +1,286 @@
-use serde::{Deserialize};
+use std::net::IpAddr;
+
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{auth::ApiKeyScope, db::schema::{DbApiKey, DbFill, DbLedgerEntry, DbUser}, service::{Asset, CandleInterval, LedgerReason, Liquidity, MarketState, OrderType, Side, Status, UserBalance}};
 
 #[derive(Deserialize)]
 pub struct SignUp {
     pub email: String,
     pub password: String
-}
\ No newline at end of file
+}
+
+#[derive(Deserialize)]
+pub struct Login {
+    pub email: String,
+    pub password: String
+}
+
+//user as returned to clients, never includes the password hash
+#[derive(Serialize)]
+pub struct UserResponse {
+    pub id: Uuid,
+    pub email: String,
+    pub created_at: DateTime<Utc>
+}
+
+#[derive(Serialize)]
+pub struct AuthResponse {
+    pub user: UserResponse,
+    pub token: String
+}
+
+impl AuthResponse {
+    pub fn new(user: DbUser, token: String) -> Self {
+        Self {
+            user: UserResponse {
+                id: user.id,
+                email: user.email,
+                created_at: user.created_at
+            },
+            token: token
+        }
+    }
+}
+
+//the user comes from the auth token, not the body
+#[derive(Deserialize)]
+pub struct CreateOrder {
+    pub order_type: OrderType,
+    pub side: Side,
+    #[serde(default)]
+    pub limit_price: BigDecimal,
+    #[serde(default)]
+    pub base_qty: BigDecimal,
+    #[serde(default)]
+    pub quote_qty: BigDecimal
+}
+
+//orders are accepted before the engine runs them, the id tracks them on the private feed and in GET /orders
+#[derive(Serialize)]
+pub struct CreateOrderResponse {
+    pub order_id: Uuid
+}
+
+#[derive(Deserialize)]
+pub struct SetMarketState {
+    pub state: MarketState
+}
+
+#[derive(Deserialize)]
+pub struct CreateApiKey {
+    pub label: Option<String>,
+    pub scopes: Vec<ApiKeyScope>,
+    //empty allows any address
+    #[serde(default)]
+    pub allowed_ips: Vec<IpAddr>
+}
+
+//api key as listed to its owner, without the secret
+#[derive(Serialize)]
+pub struct ApiKeyResponse {
+    pub id: Uuid,
+    pub api_key: String,
+    pub label: Option<String>,
+    pub scopes: Vec<String>,
+    pub allowed_ips: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>
+}
+
+impl From<DbApiKey> for ApiKeyResponse {
+    fn from(db_api_key: DbApiKey) -> Self {
+        Self {
+            id: db_api_key.id,
+            api_key: db_api_key.api_key,
+            label: db_api_key.label,
+            scopes: db_api_key.scopes,
+            allowed_ips: db_api_key.allowed_ips,
+            created_at: db_api_key.created_at,
+            revoked_at: db_api_key.revoked_at
+        }
+    }
+}
+
+//the secret is only ever returned here
+#[derive(Serialize)]
+pub struct CreateApiKeyResponse {
+    #[serde(flatten)]
+    pub api_key: ApiKeyResponse,
+    pub secret: String
+}
+
+impl CreateApiKeyResponse {
+    pub fn new(db_api_key: DbApiKey, secret: String) -> Self {
+        Self {
+            api_key: ApiKeyResponse::from(db_api_key),
+            secret: secret
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct MarketQuery {
+    pub market: String
+}
+
+#[derive(Deserialize)]
+pub struct DepthQuery {
+    pub market: String,
+    pub limit: Option<usize>
+}
+
+#[derive(Deserialize)]
+pub struct TradesQuery {
+    pub market: String,
+    pub limit: Option<usize>
+}
+
+
+//start and end are unix milliseconds
+#[derive(Deserialize)]
+pub struct KlinesQuery {
+    pub market: String,
+    pub interval: CandleInterval,
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+    pub limit: Option<i64>
+}
+
+//start and end are unix milliseconds, end is exclusive.
+//cursor is the next_cursor of the previous page
+#[derive(Deserialize)]
+pub struct OrdersQuery {
+    pub market: Option<String>,
+    pub side: Option<Side>,
+    pub status: Option<Status>,
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+    pub limit: Option<i64>,
+    pub cursor: Option<String>
+}
+
+#[derive(Deserialize)]
+pub struct FillsQuery {
+    pub market: Option<String>,
+    pub side: Option<Side>,
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+    pub limit: Option<i64>,
+    pub cursor: Option<String>
+}
+
+//next_cursor is None on the last page
+#[derive(Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>
+}
+
+#[derive(Serialize)]
+pub struct FillResponse {
+    pub trade_id: Uuid,
+    pub order_id: Uuid,
+    pub market: Option<String>,
+    pub seq: Option<i64>,
+    pub side: Side,
+    pub price: BigDecimal,
+    pub quantity: BigDecimal,
+    pub fee: BigDecimal,
+    pub liquidity: Liquidity,
+    pub created_at: DateTime<Utc>
+}
+
+impl From<DbFill> for FillResponse {
+    fn from(db_fill: DbFill) -> Self {
+        Self {
+            trade_id: db_fill.trade_id,
+            order_id: db_fill.order_id,
+            market: db_fill.market,
+            seq: db_fill.seq,
+            side: db_fill.side,
+            price: db_fill.price,
+            quantity: db_fill.quantity,
+            fee: db_fill.fee,
+            liquidity: if db_fill.is_maker { Liquidity::Maker } else { Liquidity::Taker },
+            created_at: db_fill.created_at
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct AssetBalance {
+    pub asset: String,
+    pub free: BigDecimal,
+    pub locked: BigDecimal
+}
+
+#[derive(Serialize)]
+pub struct BalancesResponse {
+    pub market: String,
+    pub balances: Vec<AssetBalance>
+}
+
+impl BalancesResponse {
+    pub fn new(market: &str, user_balance: &UserBalance) -> Self {
+        let balances = Asset::all().into_iter()
+            .map(|asset| {
+                let (free, locked) = user_balance.asset(asset);
+                AssetBalance {
+                    asset: asset.symbol(market).to_string(),
+                    free: free.clone(),
+                    locked: locked.clone()
+                }
+            })
+            .collect();
+
+        Self {
+            market: market.to_string(),
+            balances: balances
+        }
+    }
+}
+
+//start and end are unix milliseconds, end is exclusive.
+//cursor is the next_cursor of the previous page
+#[derive(Deserialize)]
+pub struct LedgerQuery {
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+    pub limit: Option<i64>,
+    pub cursor: Option<String>
+}
+
+//free and locked are the balance after the change
+#[derive(Serialize)]
+pub struct LedgerEntryResponse {
+    pub id: i64,
+    pub asset: String,
+    pub free_change: BigDecimal,
+    pub locked_change: BigDecimal,
+    pub free: BigDecimal,
+    pub locked: BigDecimal,
+    //None for entries from before causes were recorded
+    pub reason: Option<LedgerReason>,
+    pub reference_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>
+}
+
+impl LedgerEntryResponse {
+    pub fn new(market: &str, db_entry: DbLedgerEntry) -> Self {
+        Self {
+            id: db_entry.id,
+            asset: db_entry.asset.symbol(market).to_string(),
+            free_change: db_entry.free_change,
+            locked_change: db_entry.locked_change,
+            free: db_entry.free,
+            locked: db_entry.locked,
+            reason: db_entry.reason,
+            reference_id: db_entry.reference_id,
+            created_at: db_entry.created_at
+        }
+    }
+}
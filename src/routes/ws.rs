@@ -0,0 +1,30 @@
+use actix_web::{Error, HttpRequest, HttpResponse, get, rt, web};
+
+use crate::{AppData, auth::{ApiKeyScope, AuthUser}, service::{PrivateWsSession, WsSession}};
+
+//protocol is documented in service::ws
+#[get("/ws")]
+pub async fn market_ws(req: HttpRequest, data: web::Data<AppData>, body: web::Payload) -> Result<HttpResponse, Error> {
+    let (response, session, stream) = actix_ws::handle(&req, body)?;
+
+    let market = data.market_data.borrow().market.clone();
+    let events = data.market_events.subscribe();
+    rt::spawn(WsSession::default(market, session, data.market_data.clone(), data.engine_tx.clone()).run(stream, events));
+
+    Ok(response)
+}
+
+//orders, fills and balances of the caller, see service::ws::private
+#[get("/ws/private")]
+pub async fn private_ws(auth: AuthUser, req: HttpRequest, data: web::Data<AppData>, body: web::Payload) -> Result<HttpResponse, Error> {
+    if !auth.has_scope(ApiKeyScope::Read) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+
+    let (response, session, stream) = actix_ws::handle(&req, body)?;
+
+    let events = data.user_feed.subscribe(auth.user_id);
+    rt::spawn(PrivateWsSession::default(session).run(stream, events));
+
+    Ok(response)
+}
@@ -1,36 +1,91 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
 use sqlx::{Pool, Postgres};
-use tokio::sync::mpsc::Receiver;
 
-use crate::{db::update_user_balance, service::UserBalance};
+use uuid::Uuid;
+
+use crate::{db::{insert_balance_ledger, update_user_balance, update_user_balances}, service::{UserBalance, Worker}};
 
 
 pub struct BalanceWorker {
-    pool: Pool<Postgres>,
-    balance_rx: Receiver<BalanceEvent>
+    pool: Pool<Postgres>
 }
 
 impl BalanceWorker {
-    pub fn default(pool: Pool<Postgres>, balance_rx: Receiver<BalanceEvent>) -> Self {
+    pub fn default(pool: Pool<Postgres>) -> Self {
         Self { 
-            pool: pool, 
-            balance_rx: balance_rx 
+            pool: pool
         }
     }
+}
+
+impl Worker for BalanceWorker {
+    type Event = BalanceEvent;
 
-    pub async fn run(&mut self) {
-        loop {
-            if let Some(cmd) = self.balance_rx.recv().await {
-                match cmd {
-                    BalanceEvent::UpdateBalance(args) => {
-                        update_user_balance(&self.pool, args).await.unwrap()
-                    }
+    const NAME: &'static str = "balance";
+
+    async fn handle(&mut self, event: &BalanceEvent) -> anyhow::Result<()> {
+        match event {
+            BalanceEvent::UpdateBalance(args) => {
+                //the ledger and the balance are written together so a retry never records a change twice
+                let mut tx = self.pool.begin().await?;
+                insert_balance_ledger(&mut *tx, std::slice::from_ref(args)).await?;
+                update_user_balance(&mut *tx, args.balance.clone()).await?;
+                tx.commit().await?;
+                Ok(())
+            }
+        }
+    }
+
+    //every update carries the full balance, so only the latest one per user is written.
+    //the ledger still gets every change, in order
+    async fn handle_batch(&mut self, events: &[BalanceEvent]) -> anyhow::Result<()> {
+        let mut updates: Vec<UpdateBalanceArgs> = Vec::with_capacity(events.len());
+        let mut latest: HashMap<Uuid, UserBalance> = HashMap::new();
+        for event in events.iter() {
+            match event {
+                BalanceEvent::UpdateBalance(args) => {
+                    updates.push(args.clone());
+                    latest.insert(args.balance.user_id, args.balance.clone());
                 }
             }
         }
+
+        let balances: Vec<UserBalance> = latest.into_values().collect();
+        let mut tx = self.pool.begin().await?;
+        insert_balance_ledger(&mut *tx, &updates).await?;
+        update_user_balances(&mut *tx, &balances).await?;
+        tx.commit().await?;
+        Ok(())
     }
 }
 
 
+#[derive(Serialize, Clone)]
 pub enum BalanceEvent {
-    UpdateBalance(UserBalance)
-}
\ No newline at end of file
+    UpdateBalance(UpdateBalanceArgs)
+}
+
+//the balance after the change, with what caused it for the ledger
+#[derive(Serialize, Clone)]
+pub struct UpdateBalanceArgs {
+    pub balance: UserBalance,
+    pub reason: LedgerReason,
+    //order for Order and Cancel, trade for Trade and Fee
+    pub reference_id: Uuid
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::Type, Clone, Copy, PartialEq)]
+#[sqlx(type_name = "varchar")]
+#[sqlx(rename_all = "PascalCase")]
+pub enum LedgerReason {
+    //funds locked for a new order, or the unused part released once it executed
+    Order,
+    //funds released when the order left the book
+    Cancel,
+    //a fill settled
+    Trade,
+    //fees credited to the fee account
+    Fee
+}
@@ -5,9 +5,18 @@ use bigdecimal::BigDecimal;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::service::{CreateOrderArgs, Order, Side};
+use crate::service::Side;
 
 
+//the two assets of the market a balance is held in
+#[derive(Debug, Serialize, Deserialize, sqlx::Type, Clone, Copy, PartialEq)]
+#[sqlx(type_name = "varchar")]
+#[sqlx(rename_all = "PascalCase")]
+pub enum Asset {
+    Base,
+    Quote,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct UserBalance {
     pub id: Uuid,
@@ -18,27 +27,44 @@ pub struct UserBalance {
     pub locked_quote_qty: BigDecimal,
 }
 
+impl Asset {
+    pub fn all() -> [Asset; 2] {
+        [Asset::Base, Asset::Quote]
+    }
+
+    //symbols come from the market name, BTC_USDC trades BTC against USDC
+    pub fn symbol<'a>(&self, market: &'a str) -> &'a str {
+        let (base, quote) = market.split_once('_').unwrap_or((market, market));
+        match self {
+            Asset::Base => base,
+            Asset::Quote => quote
+        }
+    }
+
+    pub fn from_symbol(market: &str, symbol: &str) -> Option<Asset> {
+        Asset::all().into_iter().find(|asset| asset.symbol(market).eq_ignore_ascii_case(symbol))
+    }
+}
+
 impl UserBalance {
+    //(free, locked) of one asset
+    pub fn asset(&self, asset: Asset) -> (&BigDecimal, &BigDecimal) {
+        match asset {
+            Asset::Base => (&self.free_base_qty, &self.locked_base_qty),
+            Asset::Quote => (&self.free_quote_qty, &self.locked_quote_qty)
+        }
+    }
+
     pub fn init_user_balances(balances: Vec<UserBalance>) -> anyhow::Result<HashMap<Uuid, UserBalance>>{
         let mut balance_map: HashMap<Uuid, UserBalance> = HashMap::new();
         
         for balance in balances.iter() {
-            balance_map.insert(balance.id, balance.clone());
+            balance_map.insert(balance.user_id, balance.clone());
         }
 
         Ok(balance_map)
     }
 
-    pub fn credit_locked_quote_qty(&mut self, amount: &BigDecimal) -> anyhow::Result<()> {
-        self.locked_quote_qty += amount;
-        Ok(())
-    }
-
-    pub fn credit_locked_base_qty(&mut self, amount: &BigDecimal) -> anyhow::Result<()> {
-        self.locked_base_qty += amount;
-        Ok(())
-    }
-
     pub fn lock_free_quote_qty(&mut self, amount: &BigDecimal) -> anyhow::Result<()> {
         if self.free_quote_qty < *amount {
             return Err(anyhow::anyhow!("Insufficient free quote qty to lock"));
@@ -59,39 +85,75 @@ impl UserBalance {
         Ok(())
     }
 
-    pub fn lock_funds(&mut self, args: &CreateOrderArgs) -> anyhow::Result<BigDecimal> {
-        match args.side {
+    pub fn unlock_quote_qty(&mut self, amount: &BigDecimal) -> anyhow::Result<()> {
+        if self.locked_quote_qty < *amount {
+            return Err(anyhow::anyhow!("Insufficient locked quote qty to unlock"));
+        }
+
+        self.locked_quote_qty -= amount;
+        self.free_quote_qty += amount;
+        Ok(())
+    }
+
+    pub fn unlock_base_qty(&mut self, amount: &BigDecimal) -> anyhow::Result<()> {
+        if self.locked_base_qty < *amount {
+            return Err(anyhow::anyhow!("Insufficient locked base qty to unlock"));
+        }
+
+        self.locked_base_qty -= amount;
+        self.free_base_qty += amount;
+        Ok(())
+    }
+
+    //what a resting limit order can spend: price * base qty of quote for bids, base qty for asks
+    pub fn order_funds(side: Side, limit_price: &BigDecimal, base_qty: &BigDecimal) -> BigDecimal {
+        match side {
             Side::Bid => {
-                let free_quote_qty = self.free_quote_qty.clone();
-                let quote_qty_to_lock = free_quote_qty.clone().min(args.quote_qty.clone());   
-                let deposit_amount = &args.quote_qty - quote_qty_to_lock;
-                self.credit_locked_quote_qty(&deposit_amount)?;
-                self.lock_free_quote_qty(&free_quote_qty)?;
-                Ok(deposit_amount)
+                limit_price * base_qty
             }
             Side::Ask => {
-                let free_base_qty = self.free_base_qty.clone();
-                let base_qty_to_lock = free_base_qty.clone().min(args.base_qty.clone());   
-                let deposit_amount = &args.base_qty - base_qty_to_lock;
-                self.credit_locked_base_qty(&deposit_amount)?;
-                self.lock_free_base_qty(&free_base_qty)?;  
-                Ok(deposit_amount)                    
+                base_qty.clone()
             }
         }
     }
 
-    pub fn update_balance(&mut self, side: Side, price: &BigDecimal, trade_qty: &BigDecimal) -> anyhow::Result<()> {
+    //fails without locking anything when the free balance can not cover the order
+    pub fn lock_order_funds(&mut self, side: Side, limit_price: &BigDecimal, base_qty: &BigDecimal) -> anyhow::Result<()> {
+        let amount = UserBalance::order_funds(side, limit_price, base_qty);
         match side {
             Side::Bid => {
-                self.free_base_qty += trade_qty;
+                self.lock_free_quote_qty(&amount)
+            }
+            Side::Ask => {
+                self.lock_free_base_qty(&amount)
+            }
+        }
+    }
+
+    //fee is deducted from the received asset: base for bids, quote for asks
+    pub fn update_balance(&mut self, side: Side, price: &BigDecimal, trade_qty: &BigDecimal, fee: &BigDecimal) {
+        match side {
+            Side::Bid => {
+                self.free_base_qty += trade_qty - fee;
                 self.locked_quote_qty -= &(trade_qty * price);
             }
             Side::Ask => {
-                self.free_quote_qty += &(trade_qty * price);
+                self.free_quote_qty += &(trade_qty * price) - fee;
                 self.locked_base_qty -= trade_qty;
             }
-        };
-        Ok(())
+        }
+    }
+
+    //credit a fee paid by a user on `side` into this (fee account) balance
+    pub fn collect_fee(&mut self, side: Side, fee: &BigDecimal) {
+        match side {
+            Side::Bid => {
+                self.free_base_qty += fee;
+            }
+            Side::Ask => {
+                self.free_quote_qty += fee;
+            }
+        }
     }
 
 }
\ No newline at end of file
@@ -0,0 +1,167 @@
+use bigdecimal::BigDecimal;
+use serde::{Deserialize, Serialize};
+
+use crate::service::PublicTrade;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CandleInterval {
+    #[serde(rename = "1m")]
+    OneMinute,
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "15m")]
+    FifteenMinutes,
+    #[serde(rename = "1h")]
+    OneHour,
+    #[serde(rename = "4h")]
+    FourHours,
+    #[serde(rename = "1d")]
+    OneDay,
+}
+
+//open_time and close_time are the first and last millisecond of the bucket
+#[derive(Serialize, Clone)]
+pub struct Candle {
+    pub open_time: i64,
+    pub close_time: i64,
+    pub open: BigDecimal,
+    pub high: BigDecimal,
+    pub low: BigDecimal,
+    pub close: BigDecimal,
+    pub volume: BigDecimal,
+    pub quote_volume: BigDecimal,
+    pub trade_count: i64
+}
+
+#[derive(Serialize, Clone)]
+pub struct CandleUpdate {
+    pub market: String,
+    pub interval: CandleInterval,
+    pub candle: Candle
+}
+
+//the still open candle of every interval, built from trades as the engine makes them.
+//closed candles are served from the continuous aggregates in postgres
+pub struct CandleBuilder {
+    candles: Vec<(CandleInterval, Option<Candle>, bool)>
+}
+
+impl CandleInterval {
+    pub fn all() -> [CandleInterval; 6] {
+        [
+            CandleInterval::OneMinute,
+            CandleInterval::FiveMinutes,
+            CandleInterval::FifteenMinutes,
+            CandleInterval::OneHour,
+            CandleInterval::FourHours,
+            CandleInterval::OneDay
+        ]
+    }
+
+    pub fn millis(&self) -> i64 {
+        match self {
+            CandleInterval::OneMinute => 60 * 1000,
+            CandleInterval::FiveMinutes => 5 * 60 * 1000,
+            CandleInterval::FifteenMinutes => 15 * 60 * 1000,
+            CandleInterval::OneHour => 60 * 60 * 1000,
+            CandleInterval::FourHours => 4 * 60 * 60 * 1000,
+            CandleInterval::OneDay => 24 * 60 * 60 * 1000
+        }
+    }
+
+    //continuous aggregate holding candles of this interval
+    pub fn view(&self) -> &'static str {
+        match self {
+            CandleInterval::OneMinute => "candles_1m",
+            CandleInterval::FiveMinutes => "candles_5m",
+            CandleInterval::FifteenMinutes => "candles_15m",
+            CandleInterval::OneHour => "candles_1h",
+            CandleInterval::FourHours => "candles_4h",
+            CandleInterval::OneDay => "candles_1d"
+        }
+    }
+
+    //buckets are aligned to the unix epoch, same as time_bucket for these widths
+    pub fn open_time(&self, timestamp: i64) -> i64 {
+        timestamp - timestamp.rem_euclid(self.millis())
+    }
+}
+
+impl Candle {
+    fn open(interval: CandleInterval, trade: &PublicTrade) -> Self {
+        let open_time = interval.open_time(trade.created_at);
+        Self {
+            open_time: open_time,
+            close_time: open_time + interval.millis() - 1,
+            open: trade.price.clone(),
+            high: trade.price.clone(),
+            low: trade.price.clone(),
+            close: trade.price.clone(),
+            volume: trade.quantity.clone(),
+            quote_volume: &trade.price * &trade.quantity,
+            trade_count: 1
+        }
+    }
+
+    fn add_trade(&mut self, trade: &PublicTrade) {
+        if trade.price > self.high {
+            self.high = trade.price.clone();
+        }
+        if trade.price < self.low {
+            self.low = trade.price.clone();
+        }
+        self.close = trade.price.clone();
+        self.volume += &trade.quantity;
+        self.quote_volume += &trade.price * &trade.quantity;
+        self.trade_count += 1;
+    }
+}
+
+impl CandleBuilder {
+    pub fn default() -> Self {
+        Self {
+            candles: CandleInterval::all().into_iter().map(|interval| (interval, None, false)).collect()
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = CandleBuilder::default();
+    }
+
+    //trades must arrive oldest first
+    pub fn record_trade(&mut self, trade: &PublicTrade) {
+        for (interval, candle, changed) in self.candles.iter_mut() {
+            match candle {
+                Some(candle) if candle.open_time == interval.open_time(trade.created_at) => {
+                    candle.add_trade(trade);
+                }
+                _ => {
+                    *candle = Some(Candle::open(*interval, trade));
+                }
+            }
+            *changed = true;
+        }
+    }
+
+    //candles that changed since the last call
+    pub fn take_updates(&mut self, market: &str) -> Vec<CandleUpdate> {
+        let mut updates = Vec::new();
+
+        for (interval, candle, changed) in self.candles.iter_mut() {
+            if !*changed {
+                continue;
+            }
+            *changed = false;
+
+            if let Some(candle) = candle {
+                updates.push(CandleUpdate {
+                    market: market.to_string(),
+                    interval: *interval,
+                    candle: candle.clone()
+                });
+            }
+        }
+
+        updates
+    }
+}
@@ -1,28 +1,76 @@
-use std::{collections::HashMap};
-use bigdecimal::BigDecimal;
+use std::{collections::{HashMap, VecDeque}, time::Duration};
+use bigdecimal::{BigDecimal, RoundingMode};
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use sqlx::{Pool, Postgres};
-use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::oneshot;
 use uuid::Uuid;
 
-use crate::{db::{create_order, get_all_user_balance, get_open_orders, order}, service::{BalanceEvent, InsertTradeArgs, Order, OrderEvent, OrderType, Orderbook, Side, Status, TradeEvent, UserBalance, orderbook}};
+use crate::{db::{create_engine_snapshot, create_order, get_all_user_balance, get_fee_schedule, get_last_trade_price, get_last_trade_seq, get_market_config, get_open_orders, get_recent_trades, get_trades_since, update_market_state}, service::{AuctionIndicative, BalanceEvent, CircuitBreaker, EngineReceiver, L3Snapshot, LedgerReason, MarketData, PublicTrade, RECENT_TRADES, TICKER_WINDOW_MS, EngineState, FeeSchedule, Fifo, InsertTradeArgs, MarketConfig, MarketState, MatchingAlgorithm, matching_algorithm, Order, OrderEvent, OrderType, Orderbook, Side, Status, TradeEvent, UpdateBalanceArgs, UserBalance, UserEvent, UserFeedSender, Rejection}};
+
+//how often timed status changes are checked and auction indicative prices published
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+//how long a cancel for an order not in the book waits for that order, which may still be queued
+//behind it in the orders lane, before the cancel is rejected
+const PENDING_CANCEL_TTL_MS: i64 = 10_000;
+
+//cancels waiting for their order, further ones are rejected until some arrive or expire
+const MAX_PENDING_CANCELS: usize = 10_000;
+
+//order ids remembered after their create instruction was processed, oldest forgotten first
+const MAX_ORDER_HISTORY: usize = 100_000;
 
 pub struct Engine {
+    market: String,
+    market_config: MarketConfig,
+    state: MarketState,
+    state_until: Option<i64>,
+    breaker: CircuitBreaker,
+    //a sweep stopped at the breaker, the market halts once the instruction completes
+    breaker_hit: bool,
+    //indicative uncrossing published while PreOpen, refreshed every tick
+    indicative: Option<AuctionIndicative>,
+    market_data: MarketData,
     orderbook: Orderbook,
+    matching: Box<dyn MatchingAlgorithm>,
     balances: HashMap<Uuid, UserBalance>,
-    balance_tx: Sender<BalanceEvent>,
-    trade_tx: Sender<TradeEvent>,
-    order_tx: Sender<OrderEvent>,
+    //cancels that overtook their order, order id -> (user, received at)
+    pending_cancels: HashMap<Uuid, (Uuid, i64)>,
+    //orders already processed, order id -> user. one not in the book is no longer open
+    order_history: HashMap<Uuid, Uuid>,
+    order_history_queue: VecDeque<Uuid>,
+    fee_schedule: FeeSchedule,
+    seq: i64,
+    last_trade_price: Option<BigDecimal>,
+    balance_tx: UserFeedSender<BalanceEvent>,
+    trade_tx: UserFeedSender<TradeEvent>,
+    order_tx: UserFeedSender<OrderEvent>,
     pool: Pool<Postgres>,
-    engine_rx: Receiver<EngineIx>
+    engine_rx: EngineReceiver
 }
 
 impl Engine {
-    pub fn default(balance_tx: Sender<BalanceEvent>, trade_tx: Sender<TradeEvent>,
-        order_tx: Sender<OrderEvent>, pool: Pool<Postgres>, engine_rx: Receiver<EngineIx>) -> Self {
+    pub fn default(market: String, balance_tx: UserFeedSender<BalanceEvent>, trade_tx: UserFeedSender<TradeEvent>,
+        order_tx: UserFeedSender<OrderEvent>, pool: Pool<Postgres>, engine_rx: EngineReceiver, market_data: MarketData) -> Self {
         Self { 
+            fee_schedule: FeeSchedule::default(&market),
+            market_config: MarketConfig::default(&market),
+            market: market,
+            state: MarketState::Trading,
+            state_until: None,
+            breaker: CircuitBreaker::default(),
+            breaker_hit: false,
+            indicative: None,
+            market_data: market_data,
             orderbook: Orderbook::default(), 
+            matching: Box::new(Fifo),
             balances: HashMap::new(),
+            pending_cancels: HashMap::new(),
+            order_history: HashMap::new(),
+            order_history_queue: VecDeque::new(),
+            seq: 0,
+            last_trade_price: None,
             balance_tx: balance_tx,
             trade_tx: trade_tx,
             order_tx: order_tx,
@@ -31,166 +79,818 @@ impl Engine {
         }
     }
 
-    pub fn run(&mut self) {
+    //returns after a shutdown instruction once the final snapshot is written.
+    //the event senders are dropped with the engine, which lets the workers drain and stop
+    pub fn run(&mut self) -> anyhow::Result<()> {
         let rt = tokio::runtime::Builder::new_current_thread()
             .enable_all()
-            .build().unwrap();
+            .build()?;
 
 
         rt.block_on(async move {
-            if self.init_engine().await.is_err() {
+            if let Err(e) = self.init_engine().await {
                 eprintln!("Error Occurred, Shutting Down");
-                return;
+                return Err(e);
             }
 
+            let mut ticker = tokio::time::interval(TICK_INTERVAL);
+
             loop {
-                if let Some(cmd) = self.engine_rx.recv().await {
-                    match cmd {
-                        EngineIx::CreateLimitOrder(args) => {
+                let cmd = tokio::select! {
+                    cmd = self.engine_rx.recv() => cmd,
+                    _ = ticker.tick() => {
+                        self.on_tick().await;
+                        continue;
+                    }
+                };
+
+                //every sender is gone, nothing more can arrive
+                let Some(cmd) = cmd else {
+                    return self.shutdown().await;
+                };
+
+                //apply timed state changes that are due
+                self.refresh_market_state().await;
+
+                //whatever happens to it below, the order is not waiting in a lane anymore
+                if let EngineIx::CreateLimitOrder(args) | EngineIx::CreateMarketOrder(args) = &cmd {
+                    self.remember_order(args.order_id, args.user_id);
+                }
+
+                if !self.is_allowed(&cmd) {
+                    let reason = format!("Market {} is {:?}, instruction rejected", self.market, self.state);
+                    match cmd.origin() {
+                        Some((user_id, order_id)) => {
+                            self.reject(user_id, order_id, reason);
+                        }
+                        None => {
+                            eprintln!("{}", reason);
+                        }
+                    }
+                    continue;
+                }
+
+                match cmd {
+                    EngineIx::CreateLimitOrder(args) => {
+                        if self.is_cancel_pending(&args) {
+                            self.cancel_queued_order(args).await;
+                        } else if self.state == MarketState::PreOpen {
+                            self.add_auction_order(args).await;
+                        } else {
                             self.execute_limit_order(args).await;
                         }
-                        EngineIx::CreateMarketOrder (args) => {
+                    }
+                    EngineIx::CreateMarketOrder (args) => {
+                        if self.is_cancel_pending(&args) {
+                            self.cancel_queued_order(args).await;
+                        } else {
                             self.execute_market_order(args).await;
                         }
-                        EngineIx::CancelOrder { key } => {
+                    }
+                    EngineIx::CancelOrder(args) => {
+                        self.cancel_order(args).await;
+                    }
+                    EngineIx::UpdateFeeSchedule(fee_schedule) => {
+                        self.update_fee_schedule(fee_schedule);
+                    }
+                    EngineIx::RegisterUser(user_balance) => {
+                        self.register_user(user_balance);
+                    }
+                    EngineIx::ReconcileState { market, reply } => {
+                        if market != self.market {
+                            eprintln!("Unknown market {}", market);
+                            continue;
+                        }
 
+                        let _ = reply.send(self.engine_state());
+                    }
+                    EngineIx::L3Snapshot { market, reply } => {
+                        if market != self.market {
+                            eprintln!("Unknown market {}", market);
+                            continue;
+                        }
+
+                        //dropping the reply tells the caller the feed is off
+                        if let Some(snapshot) = self.market_data.l3_snapshot(&self.market, &self.orderbook) {
+                            let _ = reply.send(snapshot);
+                        }
+                    }
+                    EngineIx::GetBalance { user_id, reply } => {
+                        //dropping the reply tells the caller the user is unknown
+                        if let Some(user_balance) = self.balances.get(&user_id) {
+                            let _ = reply.send(user_balance.clone());
+                        }
+                    }
+                    EngineIx::SetMarketState { market, state } => {
+                        if market != self.market {
+                            eprintln!("Unknown market {}", market);
+                        } else if let Err(e) = self.transition_market(state).await {
+                            eprintln!("{}", e);
                         }
                     }
+                    //everything queued before this has been processed
+                    EngineIx::Shutdown => {
+                        return self.shutdown().await;
+                    }
                 }
-            }
-        });
 
+                //halt if this instruction moved the price too far
+                self.check_circuit_breaker().await;
 
-        //construct in memory orderbook, user balances
-
-        //start loop 
+                self.publish_market_data();
+            }
+        })
     }
 
     async fn init_engine(&mut self) -> anyhow::Result<()> {
         //load db orderbook
-        let orders = get_open_orders(&self.pool).await?;
+        let orders = get_open_orders(&self.pool, &self.market).await?;
 
         //load db user balances
         let balances = get_all_user_balance(&self.pool).await?;
 
+        //resting orders were processed by an earlier run, once they leave the book they are not open
+        for order in orders.iter() {
+            self.remember_order(order.id, order.user_id);
+        }
+
         //construct in memory orderbook, user balances
         self.orderbook = Orderbook::init_orderbook(orders)?;
+        if self.market_data.is_l3_enabled() {
+            self.orderbook.enable_l3();
+        }
         
         self.balances = UserBalance::init_user_balances(balances)?;
 
+        //load fee schedule, fees can only be charged if the fee account can be credited
+        self.fee_schedule = get_fee_schedule(&self.pool, &self.market).await?;
+
+        if let Some(fee_account_id) = self.fee_schedule.fee_account_id {
+            if !self.balances.contains_key(&fee_account_id) {
+                return Err(anyhow::anyhow!("Fee account {} has no balance", fee_account_id));
+            }
+        }
+
+        //continue the trade sequence from the last persisted trade
+        self.seq = get_last_trade_seq(&self.pool, &self.market).await?;
+
+        //load slippage and price band limits, seeded with the last traded price
+        self.market_config = get_market_config(&self.pool, &self.market).await?;
+        self.state = self.market_config.state;
+        self.state_until = self.market_config.state_until;
+        self.matching = matching_algorithm(self.market_config.matching_algorithm, &self.market_config.lot_size);
+        self.last_trade_price = get_last_trade_price(&self.pool, &self.market).await?;
+
+        //recent trades and the ticker window for market data
+        let recent_trades = get_recent_trades(&self.pool, &self.market, RECENT_TRADES as i64).await?;
+        let window_trades = get_trades_since(&self.pool, &self.market, Utc::now() - chrono::Duration::milliseconds(TICKER_WINDOW_MS)).await?;
+        self.market_data.load_trades(recent_trades, window_trades);
+        self.publish_market_data();
+
         Ok(())
     }
 
-    pub async fn execute_limit_order(&mut self, args: CreateOrderArgs) {
-        //user existence check
-        if self.balances.get(&args.user_id).is_none() {
-            eprintln!("User does not exist");
+    //which instructions the market accepts in its current state
+    pub fn is_allowed(&self, cmd: &EngineIx) -> bool {
+        //admin and configuration instructions are always accepted
+        if !matches!(cmd, EngineIx::CreateLimitOrder(_) | EngineIx::CreateMarketOrder(_) | EngineIx::CancelOrder(_)) {
+            return true;
+        }
+
+        match self.state {
+            MarketState::Trading => {
+                true
+            }
+            //limit orders accumulate in the book, market orders have nothing to price against
+            MarketState::PreOpen => {
+                !matches!(cmd, EngineIx::CreateMarketOrder(_))
+            }
+            MarketState::Halted => {
+                matches!(cmd, EngineIx::CancelOrder(_)) && self.market_config.allow_cancels_when_halted
+            }
+            MarketState::Listing | MarketState::Delisted => {
+                false
+            }
+        }
+    }
+
+    //admin requested state change
+    pub async fn transition_market(&mut self, target: MarketState) -> anyhow::Result<()> {
+        if !self.state.can_transition_to(target) {
+            return Err(anyhow::anyhow!("Market {} can not go from {:?} to {:?}", self.market, self.state, target));
+        }
+
+        match target {
+            MarketState::Halted => {
+                self.halt_market(None).await;
+            }
+            MarketState::PreOpen => {
+                self.start_auction().await;
+            }
+            MarketState::Trading => {
+                //an admin asking for Trading skips the re-opening auction, they can ask for PreOpen instead.
+                //orders collected by an earlier auction may still cross, they are uncrossed first
+                self.uncross_auction().await;
+            }
+            MarketState::Delisted => {
+                self.delist_market().await;
+            }
+            MarketState::Listing => {}
+        }
+
+        Ok(())
+    }
+
+    //move to state, persisting it. `until` is when a timed state ends by itself
+    async fn set_market_state(&mut self, state: MarketState, until: Option<i64>) {
+        self.state = state;
+        self.state_until = until;
+        self.refresh_indicative();
+
+        if let Err(e) = update_market_state(&self.pool, &self.market, state, until).await {
+            eprintln!("Failed to persist market {} state: {}", self.market, e);
+        }
+
+        println!("Market {} is {:?} until {:?}", self.market, state, until);
+    }
+
+    //halt until the given timestamp, or until resumed by an admin when None
+    pub async fn halt_market(&mut self, until: Option<i64>) {
+        self.breaker.reset();
+        self.breaker_hit = false;
+        self.set_market_state(MarketState::Halted, until).await;
+    }
+
+    //reopen through a call auction when one is configured, otherwise straight to trading,
+    //uncrossing whatever an auction interrupted by the halt left crossed
+    pub async fn resume_market(&mut self) {
+        if self.market_config.auction_duration_secs > 0 {
+            self.start_auction().await;
             return;
         }
 
-        let deposit_amount = {
-            let user_balance = self.balances.get_mut(&args.user_id).unwrap();
-            
-            //lock funds
-            user_balance.lock_funds(&args).unwrap();
+        self.uncross_auction().await;
+    }
+
+    //collect orders without matching, runs until uncrossed by an admin when no duration is configured
+    pub async fn start_auction(&mut self) {
+        let duration = i64::from(self.market_config.auction_duration_secs) * 1000;
+
+        let until = if duration > 0 {
+            Some(Utc::now().timestamp_millis() + duration)
+        } else {
+            None
         };
-        
+        self.set_market_state(MarketState::PreOpen, until).await;
+    }
 
-        //determine maker_book and taker_book
-        let (maker_book, taker_book) = self.orderbook.determine_maker_taker_book(args.side);
+    //cancel every resting order, unlocking its funds, and stop the market for good
+    pub async fn delist_market(&mut self) {
+        let order_ids: Vec<Uuid> = self.orderbook.bids.values()
+            .chain(self.orderbook.asks.values())
+            .flat_map(|orders| orders.iter().map(|order| order.id))
+            .collect();
 
-        let mut quote_qty_remaining = args.base_qty.clone();
+        for order_id in order_ids {
+            if let Err(e) = self.force_cancel_order(order_id).await {
+                eprintln!("Failed to cancel order {}: {}", order_id, e);
+            }
+        }
 
-        //create user's order in db first
-        let mut user_order = create_order(&self.pool, &args).await.unwrap();
+        self.set_market_state(MarketState::Delisted, None).await;
+    }
 
-        for (price, orders) in maker_book.iter_mut() {
-            if quote_qty_remaining.eq(&BigDecimal::from(0)) {
-                break;
+    async fn refresh_market_state(&mut self) {
+        let now = Utc::now().timestamp_millis();
+        if !self.state_until.is_some_and(|until| now >= until) {
+            return;
+        }
+
+        match self.state {
+            MarketState::Halted => {
+                self.resume_market().await;
             }
+            MarketState::PreOpen => {
+                self.uncross_auction().await;
+            }
+            _ => {}
+        }
+    }
+
+    async fn on_tick(&mut self) {
+        self.refresh_market_state().await;
+        self.refresh_indicative();
+        self.expire_pending_cancels(Utc::now().timestamp_millis());
+
+        //rolls the ticker window forward even without trades, and carries the indicative equilibrium
+        self.publish_market_data();
+    }
+
+    fn refresh_indicative(&mut self) {
+        if self.state != MarketState::PreOpen {
+            self.indicative = None;
+            return;
+        }
+
+        let (price, volume) = match self.orderbook.equilibrium(self.last_trade_price.as_ref()) {
+            Some((price, volume)) => (Some(price), volume),
+            None => (None, BigDecimal::from(0))
+        };
+
+        self.indicative = Some(AuctionIndicative {
+            market: self.market.clone(),
+            price: price,
+            volume: volume,
+            ends_at: self.state_until
+        });
+    }
 
-            let crossed = match args.side {
+    //user requested cancel of one of their resting orders. cancels skip ahead of new orders,
+    //so an order not in the book and not processed yet may still be queued, it is cancelled when it arrives
+    pub async fn cancel_order(&mut self, args: CancelOrderArgs) {
+        match self.orderbook.find_order(args.order_id) {
+            Some(order) if order.user_id == args.user_id => {}
+            Some(_) => {
+                self.reject(args.user_id, args.order_id, "Order does not exist".to_string());
+                return;
+            }
+            None => {
+                match self.order_history.get(&args.order_id) {
+                    //processed but not resting: filled, cancelled or rejected
+                    Some(user_id) if *user_id == args.user_id => {
+                        self.reject(args.user_id, args.order_id, format!("Order {} is not open", args.order_id));
+                    }
+                    Some(_) => {
+                        self.reject(args.user_id, args.order_id, "Order does not exist".to_string());
+                    }
+                    None if self.pending_cancels.len() >= MAX_PENDING_CANCELS => {
+                        self.reject(args.user_id, args.order_id, "Too many pending cancels, retry later".to_string());
+                    }
+                    None => {
+                        self.pending_cancels.insert(args.order_id, (args.user_id, Utc::now().timestamp_millis()));
+                    }
+                }
+                return;
+            }
+        }
+
+        if let Err(e) = self.force_cancel_order(args.order_id).await {
+            self.reject(args.user_id, args.order_id, format!("Failed to cancel order {}: {}", args.order_id, e));
+        }
+    }
+
+    //the order row is written before the order can trade. when that fails nothing has happened
+    //yet, so the funds locked for it are released and the order is rejected
+    async fn persist_order(&mut self, args: &CreateOrderArgs, locked: Option<&BigDecimal>) -> Option<Order> {
+        let e = match create_order(&self.pool, &self.market, args).await {
+            Ok(order) => return Some(order),
+            Err(e) => e
+        };
+
+        if let (Some(amount), Some(user_balance)) = (locked, self.balances.get_mut(&args.user_id)) {
+            let unlocked = match args.side {
                 Side::Bid => {
-                    *price > args.limit_price
+                    user_balance.unlock_quote_qty(amount)
                 }
                 Side::Ask => {
-                    *price < args.limit_price
+                    user_balance.unlock_base_qty(amount)
                 }
             };
+            if let Err(e) = unlocked {
+                eprintln!("Failed to release funds of order {}: {}", args.order_id, e);
+            }
+        }
 
-            if crossed {
-                break;
+        self.reject(args.user_id, args.order_id, format!("Failed to create order: {}", e));
+        None
+    }
+
+    fn is_cancel_pending(&self, args: &CreateOrderArgs) -> bool {
+        self.pending_cancels.get(&args.order_id).is_some_and(|(user_id, _)| *user_id == args.user_id)
+    }
+
+    //the order was cancelled while queued: record it as cancelled without locking or matching anything
+    async fn cancel_queued_order(&mut self, args: CreateOrderArgs) {
+        self.pending_cancels.remove(&args.order_id);
+
+        let Some(mut user_order) = self.persist_order(&args, None).await else {
+            return;
+        };
+        user_order.status = Status::Cancelled;
+        self.order_tx.send(OrderEvent::UpdateOrder(user_order)).await.unwrap();
+    }
+
+    fn remember_order(&mut self, order_id: Uuid, user_id: Uuid) {
+        if self.order_history.insert(order_id, user_id).is_some() {
+            return;
+        }
+        self.order_history_queue.push_back(order_id);
+
+        if self.order_history_queue.len() > MAX_ORDER_HISTORY {
+            if let Some(oldest) = self.order_history_queue.pop_front() {
+                self.order_history.remove(&oldest);
             }
+        }
+    }
 
-            for (index, order) in orders.iter_mut().enumerate() {
-                if quote_qty_remaining.eq(&BigDecimal::from(0)) {
-                    break;
-                }
-                
-                let qty_left = &order.quantity - &order.filled_quantity;
+    //cancels whose order never arrived were for orders that do not exist, or are no longer open
+    fn expire_pending_cancels(&mut self, now: i64) {
+        let expired: Vec<(Uuid, Uuid)> = self.pending_cancels.iter()
+            .filter(|(_, (_, received_at))| now - received_at >= PENDING_CANCEL_TTL_MS)
+            .map(|(order_id, (user_id, _))| (*order_id, *user_id))
+            .collect();
+
+        for (order_id, user_id) in expired {
+            self.pending_cancels.remove(&order_id);
+            self.reject(user_id, order_id, "Order does not exist".to_string());
+        }
+    }
 
-                let trade_qty = qty_left.clone().min(quote_qty_remaining.clone());
+    //remove a resting order from the book and unlock what its unfilled part still holds.
+    //funds are unlocked first, so an order whose funds can not be released stays in the book
+    async fn force_cancel_order(&mut self, order_id: Uuid) -> anyhow::Result<()> {
+        let (user_id, side, price, qty_left) = match self.orderbook.find_order(order_id) {
+            Some(order) => (order.user_id, order.side, order.price.clone(), &order.quantity - &order.filled_quantity),
+            None => return Err(anyhow::anyhow!("Order does not exist"))
+        };
 
-                quote_qty_remaining -= &trade_qty;
-                order.filled_quantity += &trade_qty;
-                user_order.filled_quantity += &trade_qty;   
+        //what the order locked: price * unfilled qty for bids, unfilled qty for asks
+        let locked_amount = match side {
+            Side::Bid => {
+                &qty_left * &price
+            }
+            Side::Ask => {
+                qty_left.clone()
+            }
+        };
 
-                //update maker balance and emit balance event
-                {
-                    let maker_balance = self.balances.get_mut(&order.user_id).unwrap();
-                    maker_balance.update_balance(order.side, &order.price, &trade_qty).unwrap();
-                    self.balance_tx.send(BalanceEvent::UpdateBalance(maker_balance.clone())).await.unwrap();
+        //unlock funds
+        {
+            let user_balance = self.balances.get_mut(&user_id).unwrap();
+            match side {
+                Side::Bid => {
+                    user_balance.unlock_quote_qty(&locked_amount)?;
                 }
+                Side::Ask => {
+                    user_balance.unlock_base_qty(&locked_amount)?;
+                }
+            }
+        }
 
-                //update users balance
-                {
-                    let user_balance = self.balances.get_mut(&args.user_id).unwrap();
-                    user_balance.update_balance(args.side, &order.price, &trade_qty).unwrap();
-                }  
-
-            
-                /////emit events
-                //ws
-
-                //trade event
-                let (buy_order_id, sell_order_id) = Engine::determine_order_ids_for_trade_event(args.side, user_order.id, order.id).unwrap();
-                
-                self.trade_tx.send(TradeEvent::InsertTrade(InsertTradeArgs {
-                    buy_order_id: buy_order_id,
-                    sell_order_id: sell_order_id,
-                    price: order.price.clone(),
-                    quantity: trade_qty.clone()
-                }))
-                .await.unwrap();
-
-                //close maker_order if filled qty == qty
-                if order.filled_quantity.eq(&order.quantity) {
-                    order.status = Status::Close;
+        //put the funds back under the order if it can not be removed
+        let mut order = match self.orderbook.remove_order(order_id, side, &price) {
+            Ok(order) => order,
+            Err(e) => {
+                let user_balance = self.balances.get_mut(&user_id).unwrap();
+                match side {
+                    Side::Bid => {
+                        user_balance.lock_free_quote_qty(&locked_amount)?;
+                    }
+                    Side::Ask => {
+                        user_balance.lock_free_base_qty(&locked_amount)?;
+                    }
                 }
+                return Err(e);
+            }
+        };
+
+        //balance event
+        self.send_balance(user_id, LedgerReason::Cancel, order_id).await;
+
+        //order event
+        order.status = Status::Cancelled;
+        self.order_tx.send(OrderEvent::UpdateOrder(order)).await.unwrap();
 
-                //order event
-                self.order_tx.send(OrderEvent::UpdateOrder(order.clone())).await.unwrap();
+        Ok(())
+    }
+
+    //accept a limit order into the book without matching it
+    pub async fn add_auction_order(&mut self, args: CreateOrderArgs) {
+        //user existence check
+        if !self.balances.contains_key(&args.user_id) {
+            self.reject(args.user_id, args.order_id, "User does not exist".to_string());
+            return;
+        }
+
+        //tick and lot size check
+        if let Err(e) = self.market_config.check_increments(Some(&args.limit_price), &args.base_qty) {
+            self.reject(args.user_id, args.order_id, e.to_string());
+            return;
+        }
+
+        //price band check
+        if !self.market_config.is_within_price_band(&args.limit_price, self.last_trade_price.as_ref()) {
+            self.reject(args.user_id, args.order_id, format!("Limit price {} is outside the price band", args.limit_price));
+            return;
+        }
 
+        //lock funds, rejecting the order if the free balance can not cover it
+        {
+            let user_balance = self.balances.get_mut(&args.user_id).unwrap();
+            if let Err(e) = user_balance.lock_order_funds(args.side, &args.limit_price, &args.base_qty) {
+                self.reject(args.user_id, args.order_id, e.to_string());
+                return;
             }
+        }
+
+        let locked = UserBalance::order_funds(args.side, &args.limit_price, &args.base_qty);
+        let Some(user_order) = self.persist_order(&args, Some(&locked)).await else {
+            return;
+        };
+        self.orderbook.add_order(user_order.clone());
+
+        //balance event
+        self.send_balance(args.user_id, LedgerReason::Order, args.order_id).await;
+
+        //order event
+        self.order_tx.send(OrderEvent::UpdateOrder(user_order)).await.unwrap();
+    }
 
-            //remove all orders which are completely filled
-            orders.retain(|order| order.filled_quantity < order.quantity);
+    //execute every crossing order at the single price maximizing volume, then resume trading.
+    //a book that does not cross just resumes
+    pub async fn uncross_auction(&mut self) {
+        if let Some((price, volume)) = self.orderbook.equilibrium(self.last_trade_price.as_ref()) {
+            let fills = self.orderbook.match_at_price(&price, &volume);
+
+            for (bid, ask, trade_qty) in fills.iter() {
+                //no aggressor in an auction, the later order is treated as the taker
+                let (maker, taker) = if bid.created_at <= ask.created_at {
+                    (bid, ask)
+                } else {
+                    (ask, bid)
+                };
+
+                //release the quote locked above the uncrossing price, it goes out with the fill's balance event
+                self.release_price_improvement(bid.user_id, &bid.price, &price, trade_qty);
+                self.settle_fill(maker, taker, &price, trade_qty).await;
+
+                //order events
+                self.order_tx.send(OrderEvent::UpdateOrder(bid.clone())).await.unwrap();
+                self.order_tx.send(OrderEvent::UpdateOrder(ask.clone())).await.unwrap();
+            }
+
+            println!("Market {} uncrossed at {} volume {}", self.market, price, volume);
+        }
+
+        self.set_market_state(MarketState::Trading, None).await;
+    }
+
+    //tell the user an order or cancel of theirs was not executed
+    fn reject(&self, user_id: Uuid, order_id: Uuid, reason: String) {
+        eprintln!("{}", reason);
+
+        //there is nothing to persist, the user is only told
+        self.order_tx.publish(UserEvent::Rejected(Rejection {
+            user_id: user_id,
+            order_id: order_id,
+            reason: reason
+        }));
+    }
+
+    //the user's balance as it is now, with the order or trade that changed it for the ledger
+    async fn send_balance(&self, user_id: Uuid, reason: LedgerReason, reference_id: Uuid) {
+        let user_balance = self.balances.get(&user_id).unwrap();
+        self.balance_tx.send(BalanceEvent::UpdateBalance(UpdateBalanceArgs {
+            balance: user_balance.clone(),
+            reason: reason,
+            reference_id: reference_id
+        }))
+        .await.unwrap();
+    }
+
+    //a bid locks its limit price, unlock what a fill at a better price did not spend
+    fn release_price_improvement(&mut self, user_id: Uuid, limit_price: &BigDecimal, price: &BigDecimal, trade_qty: &BigDecimal) {
+        if price >= limit_price {
+            return;
+        }
+
+        let price_improvement = (limit_price - price) * trade_qty;
+        let user_balance = self.balances.get_mut(&user_id).unwrap();
+        if let Err(e) = user_balance.unlock_quote_qty(&price_improvement) {
+            eprintln!("Failed to release price improvement of user {}: {}", user_id, e);
+        }
+    }
+
+    //settle one fill between a resting maker order and an incoming taker order
+    async fn settle_fill(&mut self, maker: &Order, taker: &Order, price: &BigDecimal, trade_qty: &BigDecimal) {
+        let maker_fee = self.fee_schedule.maker_fee(&maker.user_id, maker.side, price, trade_qty);
+        let taker_fee = self.fee_schedule.taker_fee(&taker.user_id, taker.side, price, trade_qty);
+        let trade_id = Uuid::new_v4();
+
+        //update maker balance and emit balance event
+        self.balances.get_mut(&maker.user_id).unwrap().update_balance(maker.side, price, trade_qty, &maker_fee);
+        self.send_balance(maker.user_id, LedgerReason::Trade, trade_id).await;
+
+        //update taker balance and emit balance event
+        self.balances.get_mut(&taker.user_id).unwrap().update_balance(taker.side, price, trade_qty, &taker_fee);
+        self.send_balance(taker.user_id, LedgerReason::Trade, trade_id).await;
+
+        //credit fee account and emit balance event
+        if let Some(fee_account_id) = self.fee_schedule.fee_account_id {
+            let fee_balance = self.balances.get_mut(&fee_account_id).unwrap();
+            fee_balance.collect_fee(maker.side, &maker_fee);
+            fee_balance.collect_fee(taker.side, &taker_fee);
+            self.send_balance(fee_account_id, LedgerReason::Fee, trade_id).await;
+        }
+
+        //trade event
+        let (buy_order_id, sell_order_id) = Engine::determine_order_ids_for_trade_event(taker.side, taker.id, maker.id).unwrap();
+        let (buyer_user_id, seller_user_id) = Engine::determine_user_ids_for_trade_event(taker.side, taker.user_id, maker.user_id).unwrap();
+        let (buyer_fee, seller_fee) = Engine::determine_fees_for_trade_event(taker.side, taker_fee, maker_fee).unwrap();
+        self.seq += 1;
+        self.last_trade_price = Some(price.clone());
+
+        let now = Utc::now().timestamp_millis();
+        self.breaker.record_trade(&self.market_config, now, price);
+        self.market_data.record_trade(PublicTrade {
+            market: self.market.clone(),
+            seq: self.seq,
+            price: price.clone(),
+            quantity: trade_qty.clone(),
+            taker_side: taker.side,
+            created_at: now
+        });
+        self.trade_tx.send(TradeEvent::InsertTrade(InsertTradeArgs {
+            id: trade_id,
+            buy_order_id: buy_order_id,
+            sell_order_id: sell_order_id,
+            price: price.clone(),
+            quantity: trade_qty.clone(),
+            buyer_fee: buyer_fee,
+            seller_fee: seller_fee,
+            market: self.market.clone(),
+            buyer_user_id: buyer_user_id,
+            seller_user_id: seller_user_id,
+            maker_order_id: maker.id,
+            taker_side: taker.side,
+            seq: self.seq,
+            created_at: now
+        }))
+        .await.unwrap();
+    }
+
+    async fn check_circuit_breaker(&mut self) {
+        if self.state != MarketState::Trading {
+            return;
+        }
+
+        let now = Utc::now().timestamp_millis();
+        if self.breaker_hit || self.breaker.is_tripped(&self.market_config, now) {
+            let cooldown = i64::from(self.market_config.breaker_cooldown_secs) * 1000;
+            self.halt_market(Some(now + cooldown)).await;
+        }
+    }
+
+    //checked before each level of a sweep that started at first_price, so one order can not
+    //trade through the breaker. flags the halt, which happens once the instruction completes
+    fn sweep_trips_breaker(&mut self, first_price: &BigDecimal, price: &BigDecimal) -> bool {
+        let now = Utc::now().timestamp_millis();
+        if !self.breaker.would_trip(&self.market_config, now, first_price, price) {
+            return false;
+        }
+
+        self.breaker_hit = true;
+        true
+    }
+
+    fn publish_market_data(&mut self) {
+        self.market_data.publish(&self.market, self.state, self.seq, &mut self.orderbook, self.indicative.clone(), Utc::now().timestamp_millis());
+    }
+
+    //persist the final in-memory state before the engine stops
+    async fn shutdown(&mut self) -> anyhow::Result<()> {
+        let orderbook = serde_json::to_value(&self.orderbook)?;
+        let balances = serde_json::to_value(&self.balances)?;
+
+        create_engine_snapshot(&self.pool, &self.market, self.seq, self.state, orderbook, balances).await?;
+
+        println!("Market {} engine stopped at seq {}", self.market, self.seq);
+        Ok(())
+    }
+
+    //in-memory balances and resting orders, checked against postgres off the engine thread
+    fn engine_state(&self) -> EngineState {
+        let orders: Vec<Order> = self.orderbook.bids.values()
+            .chain(self.orderbook.asks.values())
+            .flat_map(|orders| orders.iter().cloned())
+            .collect();
+
+        EngineState {
+            balances: self.balances.values().cloned().collect(),
+            orders: orders
+        }
+    }
+
+    //new accounts join with the balance row created at signup
+    pub fn register_user(&mut self, user_balance: UserBalance) {
+        if self.balances.contains_key(&user_balance.user_id) {
+            eprintln!("User {} is already registered", user_balance.user_id);
+            return;
+        }
+
+        self.balances.insert(user_balance.user_id, user_balance);
+    }
+
+    pub fn update_fee_schedule(&mut self, fee_schedule: FeeSchedule) {
+        if let Some(fee_account_id) = fee_schedule.fee_account_id {
+            if !self.balances.contains_key(&fee_account_id) {
+                eprintln!("Fee account {} has no balance, keeping current fee schedule", fee_account_id);
+                return;
+            }
+        }
+
+        self.fee_schedule = fee_schedule;
+    }
+
+    pub async fn execute_limit_order(&mut self, args: CreateOrderArgs) {
+        //user existence check
+        if self.balances.get(&args.user_id).is_none() {
+            self.reject(args.user_id, args.order_id, "User does not exist".to_string());
+            return;
+        }
+
+        //tick and lot size check
+        if let Err(e) = self.market_config.check_increments(Some(&args.limit_price), &args.base_qty) {
+            self.reject(args.user_id, args.order_id, e.to_string());
+            return;
+        }
+
+        //price band check
+        if !self.market_config.is_within_price_band(&args.limit_price, self.last_trade_price.as_ref()) {
+            self.reject(args.user_id, args.order_id, format!("Limit price {} is outside the price band", args.limit_price));
+            return;
+        }
+
+        //lock funds, rejecting the order if the free balance can not cover it
+        {
+            let user_balance = self.balances.get_mut(&args.user_id).unwrap();
+            if let Err(e) = user_balance.lock_order_funds(args.side, &args.limit_price, &args.base_qty) {
+                self.reject(args.user_id, args.order_id, e.to_string());
+                return;
+            }
+        }
+
+        let zero = BigDecimal::from(0);
+        let maker_side = Engine::opposite_side(args.side);
+
+        //best prices first, only those crossing the limit price
+        let prices = self.orderbook.crossing_prices(maker_side, Some(&args.limit_price));
+
+        let mut quote_qty_remaining = args.base_qty.clone();
+
+        //create user's order in db first
+        let locked = UserBalance::order_funds(args.side, &args.limit_price, &args.base_qty);
+        let Some(mut user_order) = self.persist_order(&args, Some(&locked)).await else {
+            return;
+        };
+
+        //balance event for the lock, fills below emit their own
+        self.send_balance(args.user_id, LedgerReason::Order, args.order_id).await;
+
+        let mut fills: Vec<(Order, BigDecimal)> = Vec::new();
+
+        for price in prices.iter() {
+            if quote_qty_remaining <= zero {
+                break;
+            }
+
+            //the rest of the order rests in the book, uncrossed when the market resumes
+            if self.sweep_trips_breaker(&prices[0], price) {
+                break;
+            }
+
+            //split across the level by the market's matching algorithm
+            let level_fills = self.orderbook.fill_level(maker_side, price, &quote_qty_remaining, self.matching.as_ref());
+
+            for (_, trade_qty) in level_fills.iter() {
+                quote_qty_remaining -= trade_qty;
+                user_order.filled_quantity += trade_qty;
+            }
+
+            fills.extend(level_fills);
+        }
+
+        //settle fills and emit maker order events
+        for (maker_order, trade_qty) in fills.iter() {
+            //released before settling, so it goes out with the fill's balance event
+            if args.side == Side::Bid {
+                self.release_price_improvement(args.user_id, &args.limit_price, &maker_order.price, trade_qty);
+            }
+            self.settle_fill(maker_order, &user_order, &maker_order.price, trade_qty).await;
+            self.order_tx.send(OrderEvent::UpdateOrder(maker_order.clone())).await.unwrap();
         }
 
         //if quote_qty_remaining > 0 add user order in taker book
-        if quote_qty_remaining > BigDecimal::from(0) {
-            self.orderbook.add_order(user_order.clone()).unwrap();
+        if quote_qty_remaining > zero {
+            self.orderbook.add_order(user_order.clone());
         } else {
             user_order.status = Status::Close;
         }
     
         ////emit event
-        //ws
+        //ws subscribers got the trades from settle_fill, depth and ticker go out once the instruction completes.
+        //the balance went out with the lock and each fill
 
-        //balance event
-        let user_balance = self.balances.get_mut(&args.user_id).unwrap();
-        self.balance_tx.send(BalanceEvent::UpdateBalance(user_balance.clone())).await.unwrap();
-        
         //order event
         self.order_tx.send(OrderEvent::UpdateOrder(user_order.clone())).await.unwrap();
     }
@@ -198,122 +898,179 @@ impl Engine {
 
     pub async fn execute_market_order(&mut self, args: CreateOrderArgs) {
         //user existence check
-        if self.balances.get(&args.user_id).is_none() {
-            eprintln!("User does not exist");
+        if !self.balances.contains_key(&args.user_id) {
+            self.reject(args.user_id, args.order_id, "User does not exist".to_string());
             return;
         }
 
-        let deposit_amount = {
-            let user_balance = self.balances.get_mut(&args.user_id).unwrap();
-            
-            //lock funds
-            user_balance.lock_funds(&args).unwrap();
-        };
-        
+        //lot size check, a quote-sized buy fills whole lots below
+        if let Err(e) = self.market_config.check_increments(None, &args.base_qty) {
+            self.reject(args.user_id, args.order_id, e.to_string());
+            return;
+        }
 
-        //determine maker_book and taker_book
-        let (maker_book, taker_book) = self.orderbook.determine_maker_taker_book(args.side);
+        let zero = BigDecimal::from(0);
 
-        let mut quote_qty_remaining = args.base_qty.clone();
+        //worst price this order may reach before it stops sweeping the book
+        let slippage_limit = self.market_config.slippage_limit(
+            args.side, 
+            self.orderbook.best_price(Engine::opposite_side(args.side)), 
+            self.last_trade_price.as_ref()
+        );
 
-        //create user's order in db first
-        let mut user_order = create_order(&self.pool, &args).await.unwrap();
+        //market buys are sized by base qty, or by quote qty to spend when no base qty is given
+        let sized_by_quote = args.side == Side::Bid && args.base_qty == zero;
 
-        for (price, orders) in maker_book.iter_mut() {
-            if quote_qty_remaining.eq(&BigDecimal::from(0)) {
-                break;
+        //amount to lock: quote to spend for buys, base to sell for sells
+        let lock_amount = match args.side {
+            Side::Bid => {
+                if sized_by_quote {
+                    args.quote_qty.clone()
+                } else {
+                    self.orderbook.estimate_quote_cost(&args.base_qty, slippage_limit.as_ref())
+                }
+            }
+            Side::Ask => {
+                args.base_qty.clone()
             }
+        };
 
-            {
-                let user_balance = self.balances.get(&args.user_id).unwrap();
-                if user_balance.locked_quote_qty < *price {
-                    break;
-                }
-            }        
+        if lock_amount <= zero {
+            self.reject(args.user_id, args.order_id, "Market order has no size or there is no liquidity".to_string());
+            return;
+        }
 
+        //lock funds, rejecting the order if the free balance can not cover it
+        {
+            let user_balance = self.balances.get_mut(&args.user_id).unwrap();
 
-            for (_index, order) in orders.iter_mut().enumerate() {
-                if quote_qty_remaining.eq(&BigDecimal::from(0)) {
-                    break;
+            let locked = match args.side {
+                Side::Bid => {
+                    user_balance.lock_free_quote_qty(&lock_amount)
+                }
+                Side::Ask => {
+                    user_balance.lock_free_base_qty(&lock_amount)
                 }
+            };
 
-                let qty_left = &order.quantity - &order.filled_quantity;
+            if let Err(e) = locked {
+                self.reject(args.user_id, args.order_id, e.to_string());
+                return;
+            }
+        }
 
-                let mut trade_qty = qty_left.clone().min(quote_qty_remaining.clone());
+        let maker_side = Engine::opposite_side(args.side);
 
-                {
-                    let user_balance = self.balances.get(&args.user_id).unwrap();
+        //best prices first, stopping at the slippage limit
+        let prices = self.orderbook.crossing_prices(maker_side, slippage_limit.as_ref());
 
-                    if user_balance.locked_quote_qty < *price {
-                        break;
-                    }
+        let mut base_qty_remaining = args.base_qty.clone();
+        let mut quote_qty_remaining = match args.side {
+            Side::Bid => {
+                lock_amount.clone()
+            }
+            Side::Ask => {
+                zero.clone()
+            }
+        };
 
-                    let quote_qty_to_pay = &order.price * &trade_qty; 
-                    if quote_qty_to_pay > user_balance.locked_quote_qty {
-                        trade_qty = &user_balance.locked_quote_qty / &order.price;
-                    }
-                }
+        //create user's order in db first
+        let Some(mut user_order) = self.persist_order(&args, Some(&lock_amount)).await else {
+            return;
+        };
 
-                quote_qty_remaining -= &trade_qty;
-                order.filled_quantity += &trade_qty;
-                user_order.filled_quantity += &trade_qty;   
+        //balance event for the lock, fills below emit their own
+        self.send_balance(args.user_id, LedgerReason::Order, args.order_id).await;
 
-                //update maker balance and emit balance event
-                {
-                    let maker_balance = self.balances.get_mut(&order.user_id).unwrap();
-                    maker_balance.update_balance(order.side, &order.price, &trade_qty).unwrap();
-                    self.balance_tx.send(BalanceEvent::UpdateBalance(maker_balance.clone())).await.unwrap();
-                }
+        let mut fills: Vec<(Order, BigDecimal)> = Vec::new();
 
-                //update users balance
-                {
-                    let user_balance = self.balances.get_mut(&args.user_id).unwrap();
-                    user_balance.update_balance(args.side, &order.price, &trade_qty).unwrap();
-                }  
-
-            
-                /////emit events
-                //ws
-
-                //trade event
-                let (buy_order_id, sell_order_id) = Engine::determine_order_ids_for_trade_event(args.side, user_order.id, order.id).unwrap();
-                self.trade_tx.send(TradeEvent::InsertTrade(InsertTradeArgs {
-                    buy_order_id: buy_order_id,
-                    sell_order_id: sell_order_id,
-                    price: order.price.clone(),
-                    quantity: trade_qty.clone()
-                }))
-                .await.unwrap();
-
-                //close maker_order if filled qty == qty
-                if order.filled_quantity.eq(&order.quantity) {
-                    order.status = Status::Close;
-                }
+        for price in prices.iter() {
+            let mut level_qty = base_qty_remaining.clone();
+
+            //a buy never spends more than it locked, and buys whole lots
+            if args.side == Side::Bid {
+                let lot_size = &self.market_config.lot_size;
+                let affordable_qty = (&quote_qty_remaining / price / lot_size).with_scale_round(0, RoundingMode::Down) * lot_size;
+                level_qty = if sized_by_quote {
+                    affordable_qty
+                } else {
+                    level_qty.min(affordable_qty)
+                };
+            }
+
+            //nothing left to fill or spend
+            if level_qty <= zero {
+                break;
+            }
+
+            //the rest of the order is refunded below
+            if self.sweep_trips_breaker(&prices[0], price) {
+                break;
+            }
 
-                //order event
-                self.order_tx.send(OrderEvent::UpdateOrder(order.clone())).await.unwrap();
+            //split across the level by the market's matching algorithm
+            let level_fills = self.orderbook.fill_level(maker_side, price, &level_qty, self.matching.as_ref());
+            let filled_qty: BigDecimal = level_fills.iter().map(|(_, trade_qty)| trade_qty).sum();
 
+            if args.side == Side::Bid {
+                quote_qty_remaining -= &filled_qty * price;
+            }
+            if !sized_by_quote {
+                base_qty_remaining -= &filled_qty;
             }
+            user_order.filled_quantity += &filled_qty;
 
-            //remove all orders which are completely filled
-            orders.retain(|order| order.filled_quantity < order.quantity);
+            fills.extend(level_fills);
         }
 
-        //close this user order
+        //settle fills and emit maker order events
+        for (maker_order, trade_qty) in fills.iter() {
+            self.settle_fill(maker_order, &user_order, &maker_order.price, trade_qty).await;
+            self.order_tx.send(OrderEvent::UpdateOrder(maker_order.clone())).await.unwrap();
+        }
+
+        //refund the part of the lock this order did not consume
+        {
+            let user_balance = self.balances.get_mut(&args.user_id).unwrap();
+            match args.side {
+                Side::Bid => {
+                    user_balance.unlock_quote_qty(&quote_qty_remaining).unwrap();
+                }
+                Side::Ask => {
+                    user_balance.unlock_base_qty(&base_qty_remaining).unwrap();
+                }
+            }
+        }
+
+        //close this user order. a quote-sized buy is created without a base quantity,
+        //what it bought becomes its quantity so filled never exceeds it
+        if sized_by_quote {
+            user_order.quantity = user_order.filled_quantity.clone();
+        }
         user_order.status = Status::Close;
         
         ////emit event
-        //ws
+        //ws subscribers got the trades from settle_fill, depth and ticker go out once the instruction completes
 
-        //balance event
-        let user_balance = self.balances.get_mut(&args.user_id).unwrap();
-        self.balance_tx.send(BalanceEvent::UpdateBalance(user_balance.clone())).await.unwrap();
+        //balance event for the refund
+        self.send_balance(args.user_id, LedgerReason::Order, args.order_id).await;
         
         //order event
         self.order_tx.send(OrderEvent::UpdateOrder(user_order.clone())).await.unwrap();
     }
 
 
+    pub fn opposite_side(side: Side) -> Side {
+        match side {
+            Side::Bid => {
+                Side::Ask
+            }
+            Side::Ask => {
+                Side::Bid
+            }
+        }
+    }
+
     pub fn determine_order_ids_for_trade_event(side: Side, user_order_id: Uuid, 
             matching_order_id: Uuid) -> anyhow::Result<(Uuid, Uuid)> {
         Ok(match side {
@@ -326,26 +1083,79 @@ impl Engine {
         })
     }
 
-    pub fn is_balance_exhausted(&self, user_id: &Uuid, price: &BigDecimal) -> bool {
-        let user_balance = self.balances.get(user_id).unwrap();
-        return *price > user_balance.locked_quote_qty;
+    pub fn determine_user_ids_for_trade_event(side: Side, user_id: Uuid, 
+            matching_user_id: Uuid) -> anyhow::Result<(Uuid, Uuid)> {
+        Ok(match side {
+            Side::Bid => {
+                (user_id, matching_user_id)
+            }
+            Side::Ask => {
+                (matching_user_id, user_id)
+            }
+        })
     }
 
-    
-
+    pub fn determine_fees_for_trade_event(side: Side, taker_fee: BigDecimal, 
+            maker_fee: BigDecimal) -> anyhow::Result<(BigDecimal, BigDecimal)> {
+        Ok(match side {
+            Side::Bid => {
+                (taker_fee, maker_fee)
+            }
+            Side::Ask => {
+                (maker_fee, taker_fee)
+            }
+        })
+    }
+}
 
+impl EngineIx {
+    //(user, order) of instructions sent on behalf of a user
+    pub fn origin(&self) -> Option<(Uuid, Uuid)> {
+        match self {
+            EngineIx::CreateLimitOrder(args) | EngineIx::CreateMarketOrder(args) => {
+                Some((args.user_id, args.order_id))
+            }
+            EngineIx::CancelOrder(args) => {
+                Some((args.user_id, args.order_id))
+            }
+            _ => {
+                None
+            }
+        }
+    }
 }
 
 pub enum EngineIx {
     CreateLimitOrder(CreateOrderArgs),
     CreateMarketOrder(CreateOrderArgs),
-    CancelOrder {
-        key: String
+    CancelOrder(CancelOrderArgs),
+    UpdateFeeSchedule(FeeSchedule),
+    RegisterUser(UserBalance),
+    Shutdown,
+    //copy of balances and resting orders for the reconcile worker, which compares it with postgres
+    ReconcileState {
+        market: String,
+        reply: oneshot::Sender<EngineState>
+    },
+    SetMarketState {
+        market: String,
+        state: MarketState
+    },
+    L3Snapshot {
+        market: String,
+        reply: oneshot::Sender<L3Snapshot>
+    },
+    //live balance, ahead of user_balance by whatever the balance worker has not written yet
+    GetBalance {
+        user_id: Uuid,
+        reply: oneshot::Sender<UserBalance>
     }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct CreateOrderArgs {
+    //assigned when the order is accepted over http, before it is queued
+    pub order_id: Uuid,
     pub order_type: OrderType,
     pub side: Side,
     pub user_id: Uuid,
@@ -354,3 +1164,8 @@ pub struct CreateOrderArgs {
     pub quote_qty: BigDecimal
 }
 
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CancelOrderArgs {
+    pub user_id: Uuid,
+    pub order_id: Uuid
+}
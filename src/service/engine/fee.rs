@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+
+use bigdecimal::{BigDecimal, RoundingMode};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::service::Side;
+
+//fees are stored with the same scale as NUMERIC(38,18) columns
+const FEE_SCALE: i64 = 18;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FeeRates {
+    pub maker_rate: BigDecimal,
+    pub taker_rate: BigDecimal
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FeeTier {
+    pub tier: i32,
+    pub min_volume: BigDecimal,
+    pub rates: FeeRates
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FeeSchedule {
+    pub market: String,
+    pub base_rates: FeeRates,
+    pub fee_account_id: Option<Uuid>,
+    pub tiers: Vec<FeeTier>,
+    pub user_tiers: HashMap<Uuid, i32>,
+    pub overrides: HashMap<Uuid, FeeRates>
+}
+
+impl FeeRates {
+    pub fn zero() -> Self {
+        Self {
+            maker_rate: BigDecimal::from(0),
+            taker_rate: BigDecimal::from(0)
+        }
+    }
+}
+
+impl FeeSchedule {
+    pub fn default(market: &str) -> Self {
+        Self {
+            market: market.to_string(),
+            base_rates: FeeRates::zero(),
+            fee_account_id: None,
+            tiers: Vec::new(),
+            user_tiers: HashMap::new(),
+            overrides: HashMap::new()
+        }
+    }
+
+    //per user override wins over the user's volume tier, which wins over the market rates
+    pub fn rates_for(&self, user_id: &Uuid) -> &FeeRates {
+        if let Some(rates) = self.overrides.get(user_id) {
+            return rates;
+        }
+
+        if let Some(tier) = self.user_tiers.get(user_id) {
+            if let Some(fee_tier) = self.tiers.iter().find(|t| t.tier == *tier) {
+                return &fee_tier.rates;
+            }
+        }
+
+        &self.base_rates
+    }
+
+    pub fn maker_fee(&self, user_id: &Uuid, side: Side, price: &BigDecimal, trade_qty: &BigDecimal) -> BigDecimal {
+        let rate = &self.rates_for(user_id).maker_rate;
+        FeeSchedule::compute_fee(rate, side, price, trade_qty)
+    }
+
+    pub fn taker_fee(&self, user_id: &Uuid, side: Side, price: &BigDecimal, trade_qty: &BigDecimal) -> BigDecimal {
+        let rate = &self.rates_for(user_id).taker_rate;
+        FeeSchedule::compute_fee(rate, side, price, trade_qty)
+    }
+
+    //fee is charged in the asset the user receives: base for bids, quote for asks
+    pub fn compute_fee(rate: &BigDecimal, side: Side, price: &BigDecimal, trade_qty: &BigDecimal) -> BigDecimal {
+        let received = match side {
+            Side::Bid => {
+                trade_qty.clone()
+            }
+            Side::Ask => {
+                trade_qty * price
+            }
+        };
+
+        (received * rate).with_scale_round(FEE_SCALE, RoundingMode::Down)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use bigdecimal::BigDecimal;
+    use uuid::Uuid;
+
+    use crate::service::{FeeRates, FeeSchedule, FeeTier, Side};
+
+    fn dec(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    fn rates(maker_rate: &str, taker_rate: &str) -> FeeRates {
+        FeeRates {
+            maker_rate: dec(maker_rate),
+            taker_rate: dec(taker_rate)
+        }
+    }
+
+    #[test]
+    fn rates_for_prefers_override_then_tier_then_market() {
+        let mut fee_schedule = FeeSchedule::default("BTC_USDC");
+        fee_schedule.base_rates = rates("0.002", "0.004");
+        fee_schedule.tiers.push(FeeTier { tier: 1, min_volume: dec("1000"), rates: rates("0.001", "0.003") });
+
+        let overridden = Uuid::new_v4();
+        let tiered = Uuid::new_v4();
+        let unknown_tier = Uuid::new_v4();
+        let untiered = Uuid::new_v4();
+
+        fee_schedule.user_tiers.insert(overridden, 1);
+        fee_schedule.overrides.insert(overridden, rates("0", "0.0005"));
+        fee_schedule.user_tiers.insert(tiered, 1);
+        fee_schedule.user_tiers.insert(unknown_tier, 7);
+
+        assert_eq!(fee_schedule.rates_for(&overridden).taker_rate, dec("0.0005"));
+        assert_eq!(fee_schedule.rates_for(&tiered).taker_rate, dec("0.003"));
+        //a tier missing from the schedule falls back to the market rates
+        assert_eq!(fee_schedule.rates_for(&unknown_tier).taker_rate, dec("0.004"));
+        assert_eq!(fee_schedule.rates_for(&untiered).maker_rate, dec("0.002"));
+    }
+
+    #[test]
+    fn compute_fee_charges_the_received_asset() {
+        //a buyer receives base, a seller receives quote
+        assert_eq!(FeeSchedule::compute_fee(&dec("0.001"), Side::Bid, &dec("100"), &dec("2")), dec("0.002"));
+        assert_eq!(FeeSchedule::compute_fee(&dec("0.001"), Side::Ask, &dec("100"), &dec("2")), dec("0.2"));
+    }
+
+    #[test]
+    fn compute_fee_rounds_down_to_column_scale() {
+        let fee = FeeSchedule::compute_fee(&dec("0.000000000000000003"), Side::Ask, &dec("0.5"), &dec("1"));
+        assert_eq!(fee, dec("0.000000000000000001"));
+    }
+}
@@ -0,0 +1,91 @@
+use bigdecimal::BigDecimal;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::service::{Order, Side};
+
+//order by order changes to the book, without user ids.
+//price is always the price level the order rests at, trade prices are on the trade channel.
+//orders can not be amended, so an execute carrying the remaining quantity is the only modification
+#[derive(Serialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum L3Event {
+    //resting order entered the book, quantity is what is left of it
+    Add {
+        order_id: Uuid,
+        side: Side,
+        price: BigDecimal,
+        quantity: BigDecimal
+    },
+    //resting order traded quantity, it leaves the book once remaining is zero
+    Execute {
+        order_id: Uuid,
+        side: Side,
+        price: BigDecimal,
+        quantity: BigDecimal,
+        remaining: BigDecimal
+    },
+    //resting order left the book with remaining untraded
+    Cancel {
+        order_id: Uuid,
+        side: Side,
+        price: BigDecimal,
+        remaining: BigDecimal
+    },
+}
+
+#[derive(Serialize, Clone)]
+pub struct L3Order {
+    pub order_id: Uuid,
+    pub price: BigDecimal,
+    pub quantity: BigDecimal
+}
+
+//every event of one instruction, sequenced with the depth feed
+#[derive(Serialize, Clone)]
+pub struct L3Update {
+    pub market: String,
+    pub seq: i64,
+    pub prev_seq: i64,
+    pub events: Vec<L3Event>
+}
+
+//whole book as of book sequence `seq`, orders in priority order
+#[derive(Serialize, Clone)]
+pub struct L3Snapshot {
+    pub market: String,
+    pub seq: i64,
+    pub bids: Vec<L3Order>,
+    pub asks: Vec<L3Order>
+}
+
+impl L3Event {
+    pub fn add(order: &Order) -> Self {
+        L3Event::Add {
+            order_id: order.id,
+            side: order.side,
+            price: order.price.clone(),
+            quantity: &order.quantity - &order.filled_quantity
+        }
+    }
+
+    //order as it is after the fill
+    pub fn execute(order: &Order, trade_qty: &BigDecimal) -> Self {
+        L3Event::Execute {
+            order_id: order.id,
+            side: order.side,
+            price: order.price.clone(),
+            quantity: trade_qty.clone(),
+            remaining: &order.quantity - &order.filled_quantity
+        }
+    }
+
+    pub fn cancel(order: &Order) -> Self {
+        L3Event::Cancel {
+            order_id: order.id,
+            side: order.side,
+            price: order.price.clone(),
+            remaining: &order.quantity - &order.filled_quantity
+        }
+    }
+}
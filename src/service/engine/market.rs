@@ -0,0 +1,275 @@
+use std::collections::VecDeque;
+
+use bigdecimal::BigDecimal;
+use serde::{Deserialize, Serialize};
+
+use crate::service::{MatchingAlgorithmKind, Side};
+
+#[derive(Debug, Serialize, Deserialize, sqlx::Type, Clone, Copy, PartialEq)]
+#[sqlx(type_name = "varchar")]
+#[sqlx(rename_all = "PascalCase")]
+pub enum SlippageReference {
+    BestPrice,
+    LastTrade,
+}
+
+//lifecycle of a market. PreOpen collects orders for the opening call auction
+#[derive(Debug, Serialize, Deserialize, sqlx::Type, Clone, Copy, PartialEq)]
+#[sqlx(type_name = "varchar")]
+#[sqlx(rename_all = "PascalCase")]
+pub enum MarketState {
+    Listing,
+    PreOpen,
+    Trading,
+    Halted,
+    Delisted,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MarketConfig {
+    pub market: String,
+    pub state: MarketState,
+    //when a timed state ends by itself, in milliseconds
+    pub state_until: Option<i64>,
+    pub max_slippage_pct: Option<BigDecimal>,
+    pub slippage_reference: SlippageReference,
+    pub price_band_pct: Option<BigDecimal>,
+    pub breaker_move_pct: Option<BigDecimal>,
+    pub breaker_window_secs: i32,
+    pub breaker_cooldown_secs: i32,
+    pub allow_cancels_when_halted: bool,
+    pub auction_duration_secs: i32,
+    pub matching_algorithm: MatchingAlgorithmKind,
+    pub lot_size: BigDecimal,
+    pub tick_size: BigDecimal
+}
+
+//last trade prices over the breaker window
+pub struct CircuitBreaker {
+    trades: VecDeque<(i64, BigDecimal)>
+}
+
+impl MarketState {
+    //Delisted is terminal, Listing can only be entered by creating the market
+    pub fn can_transition_to(&self, target: MarketState) -> bool {
+        match self {
+            MarketState::Listing => {
+                matches!(target, MarketState::PreOpen | MarketState::Delisted)
+            }
+            MarketState::PreOpen => {
+                matches!(target, MarketState::Trading | MarketState::Halted | MarketState::Delisted)
+            }
+            MarketState::Trading => {
+                matches!(target, MarketState::PreOpen | MarketState::Halted | MarketState::Delisted)
+            }
+            MarketState::Halted => {
+                matches!(target, MarketState::PreOpen | MarketState::Trading | MarketState::Delisted)
+            }
+            MarketState::Delisted => {
+                false
+            }
+        }
+    }
+}
+
+impl MarketConfig {
+    pub fn default(market: &str) -> Self {
+        Self {
+            market: market.to_string(),
+            state: MarketState::Trading,
+            state_until: None,
+            max_slippage_pct: None,
+            slippage_reference: SlippageReference::BestPrice,
+            price_band_pct: None,
+            breaker_move_pct: None,
+            breaker_window_secs: 60,
+            breaker_cooldown_secs: 300,
+            allow_cancels_when_halted: true,
+            auction_duration_secs: 0,
+            matching_algorithm: MatchingAlgorithmKind::Fifo,
+            lot_size: BigDecimal::new(1.into(), 18),
+            tick_size: BigDecimal::new(1.into(), 18)
+        }
+    }
+
+    //worst price a market order on `side` may trade at, None if it may sweep the whole book
+    pub fn slippage_limit(&self, side: Side, best_price: Option<&BigDecimal>, last_trade_price: Option<&BigDecimal>) -> Option<BigDecimal> {
+        let max_slippage_pct = self.max_slippage_pct.as_ref()?;
+
+        //fall back to the best price when nothing has traded yet
+        let reference = match self.slippage_reference {
+            SlippageReference::BestPrice => {
+                best_price
+            }
+            SlippageReference::LastTrade => {
+                last_trade_price.or(best_price)
+            }
+        }?;
+
+        Some(MarketConfig::offset_price(side, reference, max_slippage_pct))
+    }
+
+    //limit prices more than price_band_pct away from the reference are rejected
+    pub fn is_within_price_band(&self, price: &BigDecimal, reference: Option<&BigDecimal>) -> bool {
+        let (Some(price_band_pct), Some(reference)) = (self.price_band_pct.as_ref(), reference) else {
+            return true;
+        };
+
+        let upper = MarketConfig::offset_price(Side::Bid, reference, price_band_pct);
+        let lower = MarketConfig::offset_price(Side::Ask, reference, price_band_pct);
+
+        *price >= lower && *price <= upper
+    }
+
+    //prices must be whole ticks and quantities whole lots, price is None for market orders
+    pub fn check_increments(&self, price: Option<&BigDecimal>, base_qty: &BigDecimal) -> anyhow::Result<()> {
+        let zero = BigDecimal::from(0);
+
+        if price.is_some_and(|price| price % &self.tick_size != zero) {
+            return Err(anyhow::anyhow!("Price must be a multiple of the tick size {}", self.tick_size.normalized()));
+        }
+        if base_qty % &self.lot_size != zero {
+            return Err(anyhow::anyhow!("Quantity must be a multiple of the lot size {}", self.lot_size.normalized()));
+        }
+
+        Ok(())
+    }
+
+    //move price against `side` by pct percent: up for bids, down for asks
+    fn offset_price(side: Side, price: &BigDecimal, pct: &BigDecimal) -> BigDecimal {
+        let offset = price * pct / BigDecimal::from(100);
+        match side {
+            Side::Bid => {
+                price + offset
+            }
+            Side::Ask => {
+                price - offset
+            }
+        }
+    }
+}
+
+impl CircuitBreaker {
+    pub fn default() -> Self {
+        Self {
+            trades: VecDeque::new()
+        }
+    }
+
+    pub fn record_trade(&mut self, config: &MarketConfig, timestamp: i64, price: &BigDecimal) {
+        if config.breaker_move_pct.is_none() {
+            return;
+        }
+
+        self.trades.push_back((timestamp, price.clone()));
+        self.prune(config, timestamp);
+    }
+
+    //true if the price moved at least breaker_move_pct percent within the window ending at now
+    pub fn is_tripped(&mut self, config: &MarketConfig, now: i64) -> bool {
+        let Some(breaker_move_pct) = config.breaker_move_pct.as_ref() else {
+            return false;
+        };
+
+        self.prune(config, now);
+
+        let (Some(low), Some(high)) = (
+            self.trades.iter().map(|(_, price)| price).min(),
+            self.trades.iter().map(|(_, price)| price).max()
+        ) else {
+            return false;
+        };
+
+        if *low <= BigDecimal::from(0) {
+            return false;
+        }
+
+        (high - low) * BigDecimal::from(100) / low >= *breaker_move_pct
+    }
+
+    //true if trading from first_price to price within one sweep would move the price at least
+    //breaker_move_pct percent within the window, checked before the trades happen
+    pub fn would_trip(&mut self, config: &MarketConfig, now: i64, first_price: &BigDecimal, price: &BigDecimal) -> bool {
+        let Some(breaker_move_pct) = config.breaker_move_pct.as_ref() else {
+            return false;
+        };
+
+        self.prune(config, now);
+
+        let prices = || self.trades.iter().map(|(_, price)| price).chain([first_price, price]);
+        let (Some(low), Some(high)) = (prices().min(), prices().max()) else {
+            return false;
+        };
+
+        if *low <= BigDecimal::from(0) {
+            return false;
+        }
+
+        (high - low) * BigDecimal::from(100) / low >= *breaker_move_pct
+    }
+
+    pub fn reset(&mut self) {
+        self.trades.clear();
+    }
+
+    fn prune(&mut self, config: &MarketConfig, now: i64) {
+        let window_start = now - i64::from(config.breaker_window_secs) * 1000;
+        while self.trades.front().is_some_and(|(timestamp, _)| *timestamp < window_start) {
+            self.trades.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use bigdecimal::BigDecimal;
+
+    use crate::service::{CircuitBreaker, MarketConfig};
+
+    fn dec(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    //trips on a 10% move within 60 seconds
+    fn config() -> MarketConfig {
+        let mut config = MarketConfig::default("BTC_USDC");
+        config.breaker_move_pct = Some(dec("10"));
+        config.breaker_window_secs = 60;
+        config
+    }
+
+    #[test]
+    fn would_trip_is_off_without_breaker_move_pct() {
+        let mut breaker = CircuitBreaker::default();
+
+        assert!(!breaker.would_trip(&MarketConfig::default("BTC_USDC"), 0, &dec("100"), &dec("1000")));
+    }
+
+    #[test]
+    fn would_trip_on_the_sweep_alone() {
+        let mut breaker = CircuitBreaker::default();
+
+        assert!(!breaker.would_trip(&config(), 0, &dec("100"), &dec("109")));
+        assert!(breaker.would_trip(&config(), 0, &dec("100"), &dec("110")));
+    }
+
+    #[test]
+    fn would_trip_counts_trades_in_the_window() {
+        let mut breaker = CircuitBreaker::default();
+        breaker.record_trade(&config(), 0, &dec("95"));
+
+        //9.47% and 10.53% above the earlier trade
+        assert!(!breaker.would_trip(&config(), 1_000, &dec("100"), &dec("104")));
+        assert!(breaker.would_trip(&config(), 1_000, &dec("100"), &dec("105")));
+    }
+
+    #[test]
+    fn would_trip_ignores_trades_before_the_window() {
+        let mut breaker = CircuitBreaker::default();
+        breaker.record_trade(&config(), 0, &dec("95"));
+
+        assert!(!breaker.would_trip(&config(), 61_000, &dec("100"), &dec("105")));
+    }
+}
@@ -0,0 +1,419 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use bigdecimal::BigDecimal;
+use serde::Serialize;
+use tokio::sync::{broadcast, watch};
+
+use crate::service::{CandleBuilder, CandleUpdate, L3Snapshot, L3Update, MarketState, Orderbook, Side};
+
+//price levels per side kept in the published snapshot
+pub const SNAPSHOT_DEPTH: usize = 500;
+//trades kept for GET /trades
+pub const RECENT_TRADES: usize = 100;
+//ticker statistics cover the trailing 24 hours
+pub const TICKER_WINDOW_MS: i64 = 24 * 60 * 60 * 1000;
+//events a slow websocket subscriber may fall behind by before it misses some
+pub const MARKET_EVENT_CAPACITY: usize = 1024;
+
+#[derive(Serialize, Clone, PartialEq)]
+pub struct DepthLevel {
+    pub price: BigDecimal,
+    pub quantity: BigDecimal,
+    pub order_count: usize
+}
+
+//trade as shown to everyone, without order or user ids
+#[derive(Serialize, Clone)]
+pub struct PublicTrade {
+    pub market: String,
+    pub seq: i64,
+    pub price: BigDecimal,
+    pub quantity: BigDecimal,
+    pub taker_side: Side,
+    pub created_at: i64
+}
+
+#[derive(Serialize, Clone, PartialEq)]
+pub struct Ticker {
+    pub market: String,
+    pub best_bid: Option<BigDecimal>,
+    pub best_ask: Option<BigDecimal>,
+    pub last_price: Option<BigDecimal>,
+    pub open_24h: Option<BigDecimal>,
+    pub high_24h: Option<BigDecimal>,
+    pub low_24h: Option<BigDecimal>,
+    pub volume_24h: BigDecimal,
+    pub quote_volume_24h: BigDecimal,
+    pub trade_count_24h: usize
+}
+
+//indicative uncrossing of a running call auction, price is None while nothing crosses
+#[derive(Serialize, Clone, PartialEq)]
+pub struct AuctionIndicative {
+    pub market: String,
+    pub price: Option<BigDecimal>,
+    pub volume: BigDecimal,
+    //None when the auction runs until an admin uncrosses it
+    pub ends_at: Option<i64>
+}
+
+//read-only view of a market published by the engine after every instruction,
+//http handlers read it without touching the engine
+#[derive(Serialize, Clone)]
+pub struct MarketSnapshot {
+    pub market: String,
+    pub state: MarketState,
+    pub seq: i64,
+    //sequence of the last depth update reflected in bids and asks
+    pub book_seq: i64,
+    pub updated_at: i64,
+    //best price first
+    pub bids: Vec<DepthLevel>,
+    pub asks: Vec<DepthLevel>,
+    //newest first
+    pub recent_trades: Vec<PublicTrade>,
+    pub ticker: Ticker,
+    //only while the market is PreOpen
+    pub auction: Option<AuctionIndicative>
+}
+
+//depth as of book sequence `seq`, the starting point for applying DepthUpdates
+#[derive(Serialize, Clone)]
+pub struct DepthSnapshot {
+    pub market: String,
+    pub seq: i64,
+    pub bids: Vec<DepthLevel>,
+    pub asks: Vec<DepthLevel>
+}
+
+//levels of the published depth that changed since the previous snapshot.
+//a level with zero quantity was removed, either emptied or pushed out of the top SNAPSHOT_DEPTH.
+//prev_seq is the seq of the depth update before it. with the L3 feed on, the book sequence also moves
+//for changes outside the published depth, so seq can be more than prev_seq + 1
+#[derive(Serialize, Clone)]
+pub struct DepthUpdate {
+    pub market: String,
+    pub seq: i64,
+    pub prev_seq: i64,
+    pub bids: Vec<DepthLevel>,
+    pub asks: Vec<DepthLevel>
+}
+
+//incremental updates pushed to websocket subscribers
+#[derive(Serialize, Clone)]
+#[serde(tag = "channel", content = "data", rename_all = "snake_case")]
+pub enum MarketEvent {
+    Depth(DepthUpdate),
+    Trade(PublicTrade),
+    Ticker(Ticker),
+    Kline(CandleUpdate),
+    L3(L3Update),
+    Auction(AuctionIndicative),
+}
+
+//trade history the engine keeps for snapshots
+pub struct MarketData {
+    recent_trades: VecDeque<PublicTrade>,
+    //a trade came in since the last publish
+    trades_changed: bool,
+    window: VecDeque<PublicTrade>,
+    //(created_at, price) of the window trades that can still become the high, or the low,
+    //once older trades leave the window. prices fall from front to back for highs, rise for lows
+    highs: VecDeque<(i64, BigDecimal)>,
+    lows: VecDeque<(i64, BigDecimal)>,
+    volume: BigDecimal,
+    quote_volume: BigDecimal,
+    candles: CandleBuilder,
+    //restarts from zero with the engine, clients resync on reconnect
+    book_seq: i64,
+    //seq of the last update sent on each feed
+    depth_seq: i64,
+    l3_seq: i64,
+    l3_enabled: bool,
+    snapshot_tx: watch::Sender<MarketSnapshot>,
+    event_tx: broadcast::Sender<MarketEvent>
+}
+
+impl MarketSnapshot {
+    pub fn default(market: &str) -> Self {
+        Self {
+            market: market.to_string(),
+            state: MarketState::Trading,
+            seq: 0,
+            book_seq: 0,
+            updated_at: 0,
+            bids: Vec::new(),
+            asks: Vec::new(),
+            recent_trades: Vec::new(),
+            ticker: Ticker {
+                market: market.to_string(),
+                best_bid: None,
+                best_ask: None,
+                last_price: None,
+                open_24h: None,
+                high_24h: None,
+                low_24h: None,
+                volume_24h: BigDecimal::from(0),
+                quote_volume_24h: BigDecimal::from(0),
+                trade_count_24h: 0
+            },
+            auction: None
+        }
+    }
+
+    //best `limit` levels per side
+    pub fn depth(&self, limit: usize) -> DepthSnapshot {
+        DepthSnapshot {
+            market: self.market.clone(),
+            seq: self.book_seq,
+            bids: self.bids.iter().take(limit).cloned().collect(),
+            asks: self.asks.iter().take(limit).cloned().collect()
+        }
+    }
+}
+
+impl MarketData {
+    pub fn default(snapshot_tx: watch::Sender<MarketSnapshot>, event_tx: broadcast::Sender<MarketEvent>, l3_enabled: bool) -> Self {
+        Self {
+            recent_trades: VecDeque::new(),
+            trades_changed: true,
+            window: VecDeque::new(),
+            highs: VecDeque::new(),
+            lows: VecDeque::new(),
+            volume: BigDecimal::from(0),
+            quote_volume: BigDecimal::from(0),
+            candles: CandleBuilder::default(),
+            book_seq: 0,
+            depth_seq: 0,
+            l3_seq: 0,
+            l3_enabled: l3_enabled,
+            snapshot_tx: snapshot_tx,
+            event_tx: event_tx
+        }
+    }
+
+    //seed from persisted trades, both oldest first
+    pub fn load_trades(&mut self, recent_trades: Vec<PublicTrade>, window_trades: Vec<PublicTrade>) {
+        self.recent_trades = recent_trades.into_iter().collect();
+        self.trades_changed = true;
+        self.window.clear();
+        self.highs.clear();
+        self.lows.clear();
+        self.volume = BigDecimal::from(0);
+        self.quote_volume = BigDecimal::from(0);
+        self.candles.clear();
+
+        //every open candle starts within the ticker window, so it can be rebuilt from it
+        for trade in window_trades.into_iter() {
+            self.candles.record_trade(&trade);
+            self.add_to_window(trade);
+        }
+        self.candles.take_updates("");
+    }
+
+    pub fn record_trade(&mut self, trade: PublicTrade) {
+        self.recent_trades.push_back(trade.clone());
+        while self.recent_trades.len() > RECENT_TRADES {
+            self.recent_trades.pop_front();
+        }
+        self.trades_changed = true;
+
+        self.candles.record_trade(&trade);
+        let _ = self.event_tx.send(MarketEvent::Trade(trade.clone()));
+        self.add_to_window(trade);
+    }
+
+    pub fn is_l3_enabled(&self) -> bool {
+        self.l3_enabled
+    }
+
+    //None when the L3 feed is off
+    pub fn l3_snapshot(&self, market: &str, orderbook: &Orderbook) -> Option<L3Snapshot> {
+        if !self.l3_enabled {
+            return None;
+        }
+
+        Some(L3Snapshot {
+            market: market.to_string(),
+            seq: self.book_seq,
+            bids: orderbook.l3_orders(Side::Bid),
+            asks: orderbook.l3_orders(Side::Ask)
+        })
+    }
+
+    //only what changed since the last publish is rebuilt: depth when the book changed,
+    //recent trades after a trade. the ticker comes from running totals
+    pub fn publish(&mut self, market: &str, state: MarketState, seq: i64, orderbook: &mut Orderbook, auction: Option<AuctionIndicative>, now: i64) {
+        self.prune(now);
+
+        let ticker = Ticker {
+            market: market.to_string(),
+            best_bid: orderbook.best_price(Side::Bid).cloned(),
+            best_ask: orderbook.best_price(Side::Ask).cloned(),
+            last_price: self.recent_trades.back().map(|trade| trade.price.clone()),
+            open_24h: self.window.front().map(|trade| trade.price.clone()),
+            high_24h: self.highs.front().map(|(_, price)| price.clone()),
+            low_24h: self.lows.front().map(|(_, price)| price.clone()),
+            volume_24h: self.volume.clone(),
+            quote_volume_24h: self.quote_volume.clone(),
+            trade_count_24h: self.window.len()
+        };
+
+        let depth = if orderbook.take_depth_changed() {
+            Some((orderbook.depth(Side::Bid, SNAPSHOT_DEPTH), orderbook.depth(Side::Ask, SNAPSHOT_DEPTH)))
+        } else {
+            None
+        };
+
+        let recent_trades = if std::mem::take(&mut self.trades_changed) {
+            Some(self.recent_trades.iter().rev().cloned().collect::<Vec<PublicTrade>>())
+        } else {
+            None
+        };
+
+        //diff against the snapshot being replaced
+        let (bids, asks, ticker_changed, auction_changed) = {
+            let previous = self.snapshot_tx.borrow();
+            let (bids, asks) = match &depth {
+                Some((bids, asks)) => {
+                    (MarketData::depth_diff(&previous.bids, bids), MarketData::depth_diff(&previous.asks, asks))
+                }
+                None => {
+                    (Vec::new(), Vec::new())
+                }
+            };
+            (bids, asks, previous.ticker != ticker, previous.auction != auction)
+        };
+
+        //the book sequence only moves when the book does, once per instruction for both feeds
+        let l3_events = orderbook.take_l3_events();
+        let depth_changed = !bids.is_empty() || !asks.is_empty();
+        if depth_changed || !l3_events.is_empty() {
+            self.book_seq += 1;
+        }
+
+        let depth_update = if depth_changed {
+            let depth_update = DepthUpdate {
+                market: market.to_string(),
+                seq: self.book_seq,
+                prev_seq: self.depth_seq,
+                bids: bids,
+                asks: asks
+            };
+            self.depth_seq = self.book_seq;
+            Some(depth_update)
+        } else {
+            None
+        };
+
+        let l3_update = if !l3_events.is_empty() {
+            let l3_update = L3Update {
+                market: market.to_string(),
+                seq: self.book_seq,
+                prev_seq: self.l3_seq,
+                events: l3_events
+            };
+            self.l3_seq = self.book_seq;
+            Some(l3_update)
+        } else {
+            None
+        };
+
+        //the snapshot goes out before the update, so a snapshot never misses an update already sent.
+        //it is modified in place, unchanged parts are not copied
+        let book_seq = self.book_seq;
+        let event_ticker = ticker_changed.then(|| ticker.clone());
+        let event_auction = auction.clone().filter(|_| auction_changed);
+        self.snapshot_tx.send_modify(|snapshot| {
+            snapshot.state = state;
+            snapshot.seq = seq;
+            snapshot.book_seq = book_seq;
+            snapshot.updated_at = now;
+            if let Some((bids, asks)) = depth {
+                snapshot.bids = bids;
+                snapshot.asks = asks;
+            }
+            if let Some(recent_trades) = recent_trades {
+                snapshot.recent_trades = recent_trades;
+            }
+            if ticker_changed {
+                snapshot.ticker = ticker;
+            }
+            if auction_changed {
+                snapshot.auction = auction;
+            }
+        });
+
+        //no receivers is fine, nobody is reading market data
+        if let Some(depth_update) = depth_update {
+            let _ = self.event_tx.send(MarketEvent::Depth(depth_update));
+        }
+        if let Some(l3_update) = l3_update {
+            let _ = self.event_tx.send(MarketEvent::L3(l3_update));
+        }
+        if let Some(ticker) = event_ticker {
+            let _ = self.event_tx.send(MarketEvent::Ticker(ticker));
+        }
+        if let Some(auction) = event_auction {
+            let _ = self.event_tx.send(MarketEvent::Auction(auction));
+        }
+
+        for update in self.candles.take_updates(market).into_iter() {
+            let _ = self.event_tx.send(MarketEvent::Kline(update));
+        }
+    }
+
+    //levels added or changed in `current`, then levels only in `previous` with zero quantity
+    fn depth_diff(previous: &[DepthLevel], current: &[DepthLevel]) -> Vec<DepthLevel> {
+        let previous: BTreeMap<&BigDecimal, &DepthLevel> = previous.iter().map(|level| (&level.price, level)).collect();
+        let current_prices: BTreeSet<&BigDecimal> = current.iter().map(|level| &level.price).collect();
+
+        let mut changes: Vec<DepthLevel> = current.iter()
+            .filter(|level| previous.get(&level.price).copied() != Some(*level))
+            .cloned()
+            .collect();
+
+        for price in previous.keys().filter(|price| !current_prices.contains(*price)) {
+            changes.push(DepthLevel {
+                price: (*price).clone(),
+                quantity: BigDecimal::from(0),
+                order_count: 0
+            });
+        }
+
+        changes
+    }
+
+    fn add_to_window(&mut self, trade: PublicTrade) {
+        self.volume += &trade.quantity;
+        self.quote_volume += &trade.quantity * &trade.price;
+
+        //older trades that are not above (below) this one can never be the high (low) again
+        while self.highs.back().is_some_and(|(_, price)| *price <= trade.price) {
+            self.highs.pop_back();
+        }
+        self.highs.push_back((trade.created_at, trade.price.clone()));
+        while self.lows.back().is_some_and(|(_, price)| *price >= trade.price) {
+            self.lows.pop_back();
+        }
+        self.lows.push_back((trade.created_at, trade.price.clone()));
+
+        self.window.push_back(trade);
+    }
+
+    fn prune(&mut self, now: i64) {
+        let window_start = now - TICKER_WINDOW_MS;
+        while self.window.front().is_some_and(|trade| trade.created_at < window_start) {
+            let trade = self.window.pop_front().unwrap();
+            self.volume -= &trade.quantity;
+            self.quote_volume -= &trade.quantity * &trade.price;
+        }
+
+        while self.highs.front().is_some_and(|(created_at, _)| *created_at < window_start) {
+            self.highs.pop_front();
+        }
+        while self.lows.front().is_some_and(|(created_at, _)| *created_at < window_start) {
+            self.lows.pop_front();
+        }
+    }
+}
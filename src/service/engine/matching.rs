@@ -0,0 +1,207 @@
+use bigdecimal::{BigDecimal, RoundingMode};
+use serde::{Deserialize, Serialize};
+
+use crate::service::Order;
+
+#[derive(Debug, Serialize, Deserialize, sqlx::Type, Clone, Copy, PartialEq)]
+#[sqlx(type_name = "varchar")]
+#[sqlx(rename_all = "PascalCase")]
+pub enum MatchingAlgorithmKind {
+    Fifo,
+    ProRata,
+    FifoProRata,
+}
+
+//splits an incoming quantity across the resting orders of a single price level
+pub trait MatchingAlgorithm: Send {
+    //fill per order, in the same order as `orders`. fills sum to min(qty, level liquidity)
+    fn allocate(&self, orders: &[Order], qty: &BigDecimal) -> Vec<BigDecimal>;
+}
+
+pub struct Fifo;
+
+pub struct ProRata {
+    pub lot_size: BigDecimal
+}
+
+//oldest order is filled first, the rest of the quantity is shared pro-rata
+pub struct FifoProRata {
+    pub lot_size: BigDecimal
+}
+
+pub fn matching_algorithm(kind: MatchingAlgorithmKind, lot_size: &BigDecimal) -> Box<dyn MatchingAlgorithm> {
+    match kind {
+        MatchingAlgorithmKind::Fifo => {
+            Box::new(Fifo)
+        }
+        MatchingAlgorithmKind::ProRata => {
+            Box::new(ProRata { lot_size: lot_size.clone() })
+        }
+        MatchingAlgorithmKind::FifoProRata => {
+            Box::new(FifoProRata { lot_size: lot_size.clone() })
+        }
+    }
+}
+
+fn qty_left(order: &Order) -> BigDecimal {
+    &order.quantity - &order.filled_quantity
+}
+
+impl MatchingAlgorithm for Fifo {
+    fn allocate(&self, orders: &[Order], qty: &BigDecimal) -> Vec<BigDecimal> {
+        let mut qty_remaining = qty.clone();
+        let mut fills: Vec<BigDecimal> = Vec::with_capacity(orders.len());
+
+        for order in orders.iter() {
+            let fill = qty_left(order).min(qty_remaining.clone());
+            qty_remaining -= &fill;
+            fills.push(fill);
+        }
+
+        fills
+    }
+}
+
+impl MatchingAlgorithm for ProRata {
+    fn allocate(&self, orders: &[Order], qty: &BigDecimal) -> Vec<BigDecimal> {
+        let zero = BigDecimal::from(0);
+        let total: BigDecimal = orders.iter().map(qty_left).sum();
+
+        //whole level is taken, no sharing needed
+        if *qty >= total {
+            return orders.iter().map(qty_left).collect();
+        }
+
+        //each order gets its share rounded down to whole lots
+        let mut fills: Vec<BigDecimal> = Vec::with_capacity(orders.len());
+        for order in orders.iter() {
+            let share = qty * qty_left(order) / &total;
+            let lots = (share / &self.lot_size).with_scale_round(0, RoundingMode::Down);
+            fills.push((lots * &self.lot_size).min(qty_left(order)));
+        }
+
+        //hand out the residual a lot at a time in time priority, the last partial lot included
+        let mut residual = qty - fills.iter().sum::<BigDecimal>();
+        while residual > zero {
+            let mut allocated = false;
+
+            for (order, fill) in orders.iter().zip(fills.iter_mut()) {
+                if residual <= zero {
+                    break;
+                }
+
+                let capacity = qty_left(order) - &*fill;
+                if capacity <= zero {
+                    continue;
+                }
+
+                let extra = self.lot_size.clone().min(residual.clone()).min(capacity);
+                *fill += &extra;
+                residual -= &extra;
+                allocated = true;
+            }
+
+            if !allocated {
+                break;
+            }
+        }
+
+        fills
+    }
+}
+
+impl MatchingAlgorithm for FifoProRata {
+    fn allocate(&self, orders: &[Order], qty: &BigDecimal) -> Vec<BigDecimal> {
+        let Some((top, rest)) = orders.split_first() else {
+            return Vec::new();
+        };
+
+        let top_fill = qty_left(top).min(qty.clone());
+        let pro_rata = ProRata { lot_size: self.lot_size.clone() };
+
+        let mut fills = vec![top_fill.clone()];
+        fills.extend(pro_rata.allocate(rest, &(qty - &top_fill)));
+        fills
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use bigdecimal::BigDecimal;
+    use uuid::Uuid;
+
+    use crate::service::{FifoProRata, MatchingAlgorithm, Order, OrderType, ProRata, Side, Status};
+
+    fn dec(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    fn decs(values: &[&str]) -> Vec<BigDecimal> {
+        values.iter().map(|value| dec(value)).collect()
+    }
+
+    //resting asks of one level, oldest first
+    fn level(quantities: &[&str]) -> Vec<Order> {
+        quantities.iter().enumerate().map(|(i, quantity)| Order {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            order_type: OrderType::Limit,
+            price: dec("100"),
+            quantity: dec(quantity),
+            filled_quantity: dec("0"),
+            side: Side::Ask,
+            status: Status::Open,
+            created_at: i as i64,
+            updated_at: i as i64
+        }).collect()
+    }
+
+    #[test]
+    fn pro_rata_rounds_shares_down_to_lots() {
+        let pro_rata = ProRata { lot_size: dec("1") };
+
+        //shares of 3.33 become 3, the residual lot goes to the oldest order
+        let fills = pro_rata.allocate(&level(&["10", "10", "10"]), &dec("10"));
+        assert_eq!(fills, decs(&["4", "3", "3"]));
+    }
+
+    #[test]
+    fn pro_rata_hands_out_residual_in_time_priority() {
+        let pro_rata = ProRata { lot_size: dec("1") };
+
+        //shares are 0, 2 and 2. the oldest order only has room for one more lot, the next one takes the other
+        let fills = pro_rata.allocate(&level(&["1", "10", "10"]), &dec("6"));
+        assert_eq!(fills, decs(&["1", "3", "2"]));
+    }
+
+    #[test]
+    fn pro_rata_allocates_everything_as_residual_below_one_lot_per_order() {
+        let pro_rata = ProRata { lot_size: dec("1") };
+
+        //every share rounds down to nothing, lots go out oldest first and the partial lot last
+        let fills = pro_rata.allocate(&level(&["10", "10", "10"]), &dec("2.5"));
+        assert_eq!(fills, decs(&["1", "1", "0.5"]));
+    }
+
+    #[test]
+    fn pro_rata_takes_the_whole_level() {
+        let pro_rata = ProRata { lot_size: dec("1") };
+
+        let fills = pro_rata.allocate(&level(&["2", "3"]), &dec("10"));
+        assert_eq!(fills, decs(&["2", "3"]));
+    }
+
+    #[test]
+    fn fifo_pro_rata_fills_the_oldest_order_first() {
+        let fifo_pro_rata = FifoProRata { lot_size: dec("1") };
+
+        let mut orders = level(&["4", "10", "10"]);
+        orders[0].filled_quantity = dec("2");
+
+        //the oldest order's 2 left are filled whole, the other 4 are shared
+        let fills = fifo_pro_rata.allocate(&orders, &dec("6"));
+        assert_eq!(fills, decs(&["2", "2", "2"]));
+    }
+}
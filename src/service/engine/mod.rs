@@ -8,4 +8,30 @@ pub mod orderbook;
 pub use orderbook::*;
 
 pub mod trade;
-pub use trade::*;
\ No newline at end of file
+pub use trade::*;
+
+pub mod fee;
+pub use fee::*;
+
+pub mod market;
+pub use market::*;
+
+pub mod matching;
+pub use matching::*;
+pub mod reconcile;
+pub use reconcile::*;
+
+pub mod queue;
+pub use queue::*;
+
+pub mod market_data;
+pub use market_data::*;
+
+pub mod candle;
+pub use candle::*;
+
+pub mod user_feed;
+pub use user_feed::*;
+
+pub mod l3;
+pub use l3::*;
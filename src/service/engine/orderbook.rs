@@ -1,16 +1,18 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
 
 use bigdecimal::BigDecimal;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::db::schema::DbOrder;
+use crate::{db::schema::DbOrder, service::{DepthLevel, L3Event, L3Order, MatchingAlgorithm}};
 
 #[derive(Debug, Serialize, Deserialize, sqlx::Type, Clone, Copy, PartialEq)]
 #[sqlx(type_name = "varchar")]
 #[sqlx(rename_all = "PascalCase")]
 pub enum Side {
+    #[serde(alias = "bid")]
     Bid,
+    #[serde(alias = "ask")]
     Ask,
 }
 
@@ -18,8 +20,11 @@ pub enum Side {
 #[sqlx(type_name = "varchar")]
 #[sqlx(rename_all = "PascalCase")]
 pub enum Status {
+    #[serde(alias = "open")]
     Open,
+    #[serde(alias = "close")]
     Close,
+    #[serde(alias = "cancelled")]
     Cancelled
 }
 
@@ -49,13 +54,28 @@ pub struct Order {
 pub struct Orderbook {
     pub bids: BTreeMap<BigDecimal, Vec<Order>>,
     pub asks: BTreeMap<BigDecimal, Vec<Order>>,
+    //changes since the last take_l3_events, None unless the L3 feed is on
+    #[serde(skip)]
+    l3_events: Option<Vec<L3Event>>,
+    //open quantity of each level, kept up to date so depth never sums orders
+    #[serde(skip)]
+    bid_totals: BTreeMap<BigDecimal, BigDecimal>,
+    #[serde(skip)]
+    ask_totals: BTreeMap<BigDecimal, BigDecimal>,
+    //a level changed since the last take_depth_changed
+    #[serde(skip)]
+    depth_changed: bool,
 }
 
 impl Orderbook {
     pub fn default() -> Self {
         Self { 
             bids: BTreeMap::new(), 
-            asks: BTreeMap::new()
+            asks: BTreeMap::new(),
+            l3_events: None,
+            bid_totals: BTreeMap::new(),
+            ask_totals: BTreeMap::new(),
+            depth_changed: true
         }
     }
 
@@ -89,7 +109,7 @@ impl Orderbook {
                         }
                         Some(order_list) => {
                             let index = order_list.partition_point(
-                                |o| o.created_at >= order.created_at
+                                |o| o.created_at <= order.created_at
                             );
                             order_list.insert(index, order.clone());
                         }
@@ -98,15 +118,28 @@ impl Orderbook {
             }
         }
 
-        let orderbook = Orderbook {
+        let mut orderbook = Orderbook {
             bids: bids,
-            asks: asks
+            asks: asks,
+            l3_events: None,
+            bid_totals: BTreeMap::new(),
+            ask_totals: BTreeMap::new(),
+            depth_changed: true
         };
 
+        for side in [Side::Bid, Side::Ask] {
+            let levels: Vec<(BigDecimal, BigDecimal)> = orderbook.book(side).iter()
+                .map(|(price, orders)| (price.clone(), orders.iter().map(|order| &order.quantity - &order.filled_quantity).sum()))
+                .collect();
+            orderbook.totals_mut(side).extend(levels);
+        }
+
         Ok(orderbook)
     }
 
-    pub fn add_order(&mut self, order: Order) -> anyhow::Result<()> {
+    pub fn add_order(&mut self, order: Order) {
+        self.record_l3(L3Event::add(&order));
+
         match order.side {
             Side::Bid => {
                 match self.bids.get_mut(&order.price) {
@@ -132,14 +165,15 @@ impl Orderbook {
                     }
                     Some(order_list) => {
                         let index = order_list.partition_point(
-                            |o| o.created_at >= order.created_at
+                            |o| o.created_at <= order.created_at
                         );
                         order_list.insert(index, order.clone());
                     }
                 }
             }
         }
-        Ok(())
+
+        self.adjust_level(order.side, &order.price, &order.quantity - &order.filled_quantity);
     }
 
     pub fn convert_db_order(db_order: &DbOrder) -> anyhow::Result<Order> {
@@ -159,7 +193,7 @@ impl Orderbook {
         Ok(order)
     }
 
-    pub fn remove_order(&mut self, order_id: Uuid, side: Side, price: &BigDecimal) -> anyhow::Result<()> {
+    pub fn remove_order(&mut self, order_id: Uuid, side: Side, price: &BigDecimal) -> anyhow::Result<Order> {
         let book = match side {
             Side::Bid => {
                 &mut self.bids
@@ -170,41 +204,453 @@ impl Orderbook {
             }
         };
 
-        match book.get_mut(price) {
+        let Some(order_list) = book.get_mut(price) else {
+            return Err(anyhow::anyhow!("Orders at price: {:?} does not exist", price));
+        };
+
+        //orders in a level are sorted by time, not id
+        let Some(index) = order_list.iter().position(|order| order.id == order_id) else {
+            return Err(anyhow::anyhow!("Order does not exist"));
+        };
+
+        let order = order_list.remove(index);
+        if order_list.is_empty() {
+            book.remove(price);
+        }
+
+        self.record_l3(L3Event::cancel(&order));
+        self.adjust_level(side, price, -(&order.quantity - &order.filled_quantity));
+        Ok(order)
+    }
+
+    pub fn find_order(&self, order_id: Uuid) -> Option<&Order> {
+        self.bids.values()
+            .chain(self.asks.values())
+            .flat_map(|orders| orders.iter())
+            .find(|order| order.id == order_id)
+    }
+
+    //quote needed to buy base_qty by walking the asks up to max_price, capped at the liquidity available
+    pub fn estimate_quote_cost(&self, base_qty: &BigDecimal, max_price: Option<&BigDecimal>) -> BigDecimal {
+        let mut cost = BigDecimal::from(0);
+        let mut qty_remaining = base_qty.clone();
+
+        for (price, orders) in self.asks.iter() {
+            if max_price.is_some_and(|max_price| price > max_price) {
+                return cost;
+            }
+
+            for order in orders.iter() {
+                if qty_remaining <= BigDecimal::from(0) {
+                    return cost;
+                }
+
+                let qty_left = &order.quantity - &order.filled_quantity;
+                let qty = qty_left.min(qty_remaining.clone());
+
+                cost += &qty * price;
+                qty_remaining -= &qty;
+            }
+        }
+
+        cost
+    }
+
+    //auction equilibrium: the price maximizing executable volume, ties broken by the smallest
+    //imbalance and then by distance to the reference price
+    pub fn equilibrium(&self, reference: Option<&BigDecimal>) -> Option<(BigDecimal, BigDecimal)> {
+        let zero = BigDecimal::from(0);
+
+        let mut candidates: Vec<&BigDecimal> = self.bids.keys().chain(self.asks.keys()).collect();
+        candidates.sort();
+        candidates.dedup();
+
+        //(price, executable volume, imbalance) of the best candidates so far
+        let mut best: Vec<(BigDecimal, BigDecimal, BigDecimal)> = Vec::new();
+
+        for price in candidates {
+            let demand: BigDecimal = self.bids.range(price..)
+                .flat_map(|(_, orders)| orders.iter())
+                .map(|order| &order.quantity - &order.filled_quantity)
+                .sum();
+            let supply: BigDecimal = self.asks.range(..=price)
+                .flat_map(|(_, orders)| orders.iter())
+                .map(|order| &order.quantity - &order.filled_quantity)
+                .sum();
+
+            let volume = demand.clone().min(supply.clone());
+            if volume <= zero {
+                continue;
+            }
+            let imbalance = (demand - supply).abs();
+
+            let is_better = match best.first() {
+                None => {
+                    true
+                }
+                Some((_, best_volume, best_imbalance)) => {
+                    volume > *best_volume || (volume == *best_volume && imbalance < *best_imbalance)
+                }
+            };
+            let is_tie = best.first().is_some_and(
+                |(_, best_volume, best_imbalance)| volume == *best_volume && imbalance == *best_imbalance
+            );
+
+            if is_better {
+                best.clear();
+                best.push((price.clone(), volume, imbalance));
+            } else if is_tie {
+                best.push((price.clone(), volume, imbalance));
+            }
+        }
+
+        //still tied: closest to the reference price, or the middle candidate without one
+        let index = match reference {
+            Some(reference) => {
+                best.iter()
+                    .enumerate()
+                    .min_by_key(|(_, (price, _, _))| (price - reference).abs())
+                    .map(|(index, _)| index)?
+            }
             None => {
-                return Err(anyhow::anyhow!("Orders at price: {:?} does not exist", price));
-            }
-
-            Some(order_list) => {
-                match order_list.binary_search_by(
-                    |order| order.price.cmp(price)
-                ) {
-                    //index must be 0
-                    Ok(index) => {
-                        let order = &order_list[index];
-                        if order.id == order_id {
-                            order_list.remove(index);
-                        } else {
-                            return Err(anyhow::anyhow!("Order does not exist"));
-                        }
-                    }
-                    Err(_) => {
-                        return Err(anyhow::anyhow!("Order does not exist"));
-                    }
-                }   
+                best.len().checked_sub(1)? / 2
+            }
+        };
+
+        let (price, volume, _) = best.swap_remove(index);
+        Some((price, volume))
+    }
+
+    //fill crossing bids and asks in price-time priority at a single price, up to volume.
+    //returns (bid, ask, quantity) per fill with both orders as they are after the fill
+    pub fn match_at_price(&mut self, price: &BigDecimal, volume: &BigDecimal) -> Vec<(Order, Order, BigDecimal)> {
+        let zero = BigDecimal::from(0);
+        let mut fills: Vec<(Order, Order, BigDecimal)> = Vec::new();
+        let mut volume_remaining = volume.clone();
+
+        //(level price, index in level) of crossing orders, best first
+        let mut bid_queue: VecDeque<(BigDecimal, usize)> = VecDeque::new();
+        for (level_price, orders) in self.bids.range(price..).rev() {
+            for index in 0..orders.len() {
+                bid_queue.push_back((level_price.clone(), index));
+            }
+        }
+
+        let mut ask_queue: VecDeque<(BigDecimal, usize)> = VecDeque::new();
+        for (level_price, orders) in self.asks.range(..=price) {
+            for index in 0..orders.len() {
+                ask_queue.push_back((level_price.clone(), index));
             }
         }
-        Ok(())
+
+        while volume_remaining > zero {
+            let (Some((bid_price, bid_index)), Some((ask_price, ask_index))) = (bid_queue.front(), ask_queue.front()) else {
+                break;
+            };
+
+            let bid = &mut self.bids.get_mut(bid_price).unwrap()[*bid_index];
+            let ask = &mut self.asks.get_mut(ask_price).unwrap()[*ask_index];
+
+            let bid_left = &bid.quantity - &bid.filled_quantity;
+            let ask_left = &ask.quantity - &ask.filled_quantity;
+            let trade_qty = bid_left.min(ask_left).min(volume_remaining.clone());
+
+            bid.filled_quantity += &trade_qty;
+            ask.filled_quantity += &trade_qty;
+            volume_remaining -= &trade_qty;
+
+            if bid.filled_quantity == bid.quantity {
+                bid.status = Status::Close;
+                bid_queue.pop_front();
+            }
+            if ask.filled_quantity == ask.quantity {
+                ask.status = Status::Close;
+                ask_queue.pop_front();
+            }
+
+            fills.push((bid.clone(), ask.clone(), trade_qty));
+        }
+
+        //remove filled orders and empty price levels
+        for book in [&mut self.bids, &mut self.asks] {
+            for orders in book.values_mut() {
+                orders.retain(|order| order.filled_quantity < order.quantity);
+            }
+            book.retain(|_, orders| !orders.is_empty());
+        }
+
+        for (bid, ask, trade_qty) in fills.iter() {
+            self.record_l3(L3Event::execute(bid, trade_qty));
+            self.record_l3(L3Event::execute(ask, trade_qty));
+            self.adjust_level(Side::Bid, &bid.price, -trade_qty);
+            self.adjust_level(Side::Ask, &ask.price, -trade_qty);
+        }
+
+        fills
     }
 
-    pub fn determine_maker_taker_book(&mut self, side: Side) -> (&mut BTreeMap<BigDecimal, Vec<Order>>, &mut BTreeMap<BigDecimal, Vec<Order>>) {
+    //price levels on `side` in priority order (highest bid, lowest ask first), only those at or better than limit
+    pub fn crossing_prices(&self, side: Side, limit: Option<&BigDecimal>) -> Vec<BigDecimal> {
         match side {
             Side::Bid => {
-                (&mut self.asks, &mut self.bids)
+                self.bids.keys()
+                    .rev()
+                    .take_while(|price| limit.is_none_or(|limit| *price >= limit))
+                    .cloned()
+                    .collect()
             }
             Side::Ask => {
-                (&mut self.bids, &mut self.asks)
+                self.asks.keys()
+                    .take_while(|price| limit.is_none_or(|limit| *price <= limit))
+                    .cloned()
+                    .collect()
             }
-        }    
+        }
     }
-}
\ No newline at end of file
+
+    //fill up to qty from the level at price on `side`, split across its orders by the matching algorithm.
+    //returns the touched orders as they are after the fill along with their fill qty
+    pub fn fill_level(&mut self, side: Side, price: &BigDecimal, qty: &BigDecimal, matching: &dyn MatchingAlgorithm) -> Vec<(Order, BigDecimal)> {
+        let book = match side {
+            Side::Bid => {
+                &mut self.bids
+            }
+            Side::Ask => {
+                &mut self.asks
+            }
+        };
+
+        let Some(orders) = book.get_mut(price) else {
+            return Vec::new();
+        };
+
+        let zero = BigDecimal::from(0);
+        let allocations = matching.allocate(orders, qty);
+
+        let mut fills: Vec<(Order, BigDecimal)> = Vec::new();
+        for (order, trade_qty) in orders.iter_mut().zip(allocations) {
+            if trade_qty <= zero {
+                continue;
+            }
+
+            order.filled_quantity += &trade_qty;
+
+            //close order if filled qty == qty
+            if order.filled_quantity == order.quantity {
+                order.status = Status::Close;
+            }
+
+            fills.push((order.clone(), trade_qty));
+        }
+
+        //remove all orders which are completely filled, and the level once empty
+        orders.retain(|order| order.filled_quantity < order.quantity);
+        if orders.is_empty() {
+            book.remove(price);
+        }
+
+        for (order, trade_qty) in fills.iter() {
+            self.record_l3(L3Event::execute(order, trade_qty));
+        }
+        let filled_qty: BigDecimal = fills.iter().map(|(_, trade_qty)| trade_qty).sum();
+        self.adjust_level(side, price, -filled_qty);
+
+        fills
+    }
+
+    pub fn best_price(&self, side: Side) -> Option<&BigDecimal> {
+        match side {
+            Side::Bid => {
+                self.bids.keys().next_back()
+            }
+            Side::Ask => {
+                self.asks.keys().next()
+            }
+        }
+    }
+
+    //aggregated levels, best price first. the totals have the same levels as the book
+    pub fn depth(&self, side: Side, limit: usize) -> Vec<DepthLevel> {
+        let level = |((price, quantity), orders): ((&BigDecimal, &BigDecimal), &Vec<Order>)| DepthLevel {
+            price: price.clone(),
+            quantity: quantity.clone(),
+            order_count: orders.len()
+        };
+
+        match side {
+            Side::Bid => {
+                self.bid_totals.iter().rev().zip(self.bids.values().rev()).take(limit).map(level).collect()
+            }
+            Side::Ask => {
+                self.ask_totals.iter().zip(self.asks.values()).take(limit).map(level).collect()
+            }
+        }
+    }
+
+    //true once after any level changed
+    pub fn take_depth_changed(&mut self) -> bool {
+        std::mem::take(&mut self.depth_changed)
+    }
+
+    fn book(&self, side: Side) -> &BTreeMap<BigDecimal, Vec<Order>> {
+        match side {
+            Side::Bid => {
+                &self.bids
+            }
+            Side::Ask => {
+                &self.asks
+            }
+        }
+    }
+
+    fn totals_mut(&mut self, side: Side) -> &mut BTreeMap<BigDecimal, BigDecimal> {
+        match side {
+            Side::Bid => {
+                &mut self.bid_totals
+            }
+            Side::Ask => {
+                &mut self.ask_totals
+            }
+        }
+    }
+
+    //apply a change of open quantity at a level, dropping the total once the level is gone from the book
+    fn adjust_level(&mut self, side: Side, price: &BigDecimal, delta: BigDecimal) {
+        let level_exists = self.book(side).contains_key(price);
+        let totals = self.totals_mut(side);
+
+        if level_exists {
+            *totals.entry(price.clone()).or_insert_with(|| BigDecimal::from(0)) += delta;
+        } else {
+            totals.remove(price);
+        }
+
+        self.depth_changed = true;
+    }
+
+    //resting orders on `side` in priority order
+    pub fn l3_orders(&self, side: Side) -> Vec<L3Order> {
+        let order = |order: &Order| L3Order {
+            order_id: order.id,
+            price: order.price.clone(),
+            quantity: &order.quantity - &order.filled_quantity
+        };
+
+        match side {
+            Side::Bid => {
+                self.bids.values().rev().flat_map(|orders| orders.iter()).map(order).collect()
+            }
+            Side::Ask => {
+                self.asks.values().flat_map(|orders| orders.iter()).map(order).collect()
+            }
+        }
+    }
+
+    pub fn enable_l3(&mut self) {
+        self.l3_events = Some(Vec::new());
+    }
+
+    pub fn is_l3_enabled(&self) -> bool {
+        self.l3_events.is_some()
+    }
+
+    pub fn take_l3_events(&mut self) -> Vec<L3Event> {
+        match self.l3_events.as_mut() {
+            Some(events) => std::mem::take(events),
+            None => Vec::new()
+        }
+    }
+
+    fn record_l3(&mut self, event: L3Event) {
+        if let Some(events) = self.l3_events.as_mut() {
+            events.push(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use bigdecimal::BigDecimal;
+    use uuid::Uuid;
+
+    use crate::service::{Order, OrderType, Orderbook, Side, Status};
+
+    fn dec(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    fn order(side: Side, price: &str, quantity: &str, created_at: i64) -> Order {
+        Order {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            order_type: OrderType::Limit,
+            price: dec(price),
+            quantity: dec(quantity),
+            filled_quantity: dec("0"),
+            side: side,
+            status: Status::Open,
+            created_at: created_at,
+            updated_at: created_at
+        }
+    }
+
+    //crosses for 2 at both 100 and 101 with an imbalance of 1, and for 1 at 102
+    fn auction_book() -> Orderbook {
+        let mut orderbook = Orderbook::default();
+        orderbook.add_order(order(Side::Bid, "102", "1", 1));
+        orderbook.add_order(order(Side::Bid, "101", "2", 2));
+        orderbook.add_order(order(Side::Ask, "100", "2", 3));
+        orderbook.add_order(order(Side::Ask, "103", "2", 4));
+        orderbook
+    }
+
+    #[test]
+    fn equilibrium_breaks_ties_by_reference_price() {
+        let orderbook = auction_book();
+
+        assert_eq!(orderbook.equilibrium(Some(&dec("101.5"))), Some((dec("101"), dec("2"))));
+        assert_eq!(orderbook.equilibrium(Some(&dec("90"))), Some((dec("100"), dec("2"))));
+    }
+
+    #[test]
+    fn equilibrium_takes_the_middle_candidate_without_reference() {
+        let orderbook = auction_book();
+
+        assert_eq!(orderbook.equilibrium(None), Some((dec("100"), dec("2"))));
+    }
+
+    #[test]
+    fn equilibrium_is_none_when_the_book_does_not_cross() {
+        let mut orderbook = Orderbook::default();
+        orderbook.add_order(order(Side::Bid, "99", "1", 1));
+        orderbook.add_order(order(Side::Ask, "100", "1", 2));
+
+        assert_eq!(orderbook.equilibrium(Some(&dec("99.5"))), None);
+    }
+
+    #[test]
+    fn match_at_price_fills_in_price_time_priority() {
+        let mut orderbook = auction_book();
+        let late_bid = order(Side::Bid, "101", "1", 5);
+        orderbook.add_order(late_bid.clone());
+
+        let fills = orderbook.match_at_price(&dec("101"), &dec("2"));
+
+        //best bid first, then the older of the two bids at 101
+        let filled: Vec<(BigDecimal, i64, BigDecimal)> = fills.iter()
+            .map(|(bid, _, trade_qty)| (bid.price.clone(), bid.created_at, trade_qty.clone()))
+            .collect();
+        assert_eq!(filled, vec![(dec("102"), 1, dec("1")), (dec("101"), 2, dec("1"))]);
+        assert!(fills.iter().all(|(_, ask, _)| ask.price == dec("100")));
+
+        //filled orders and empty levels are gone, the partly filled bid keeps its place
+        assert!(!orderbook.bids.contains_key(&dec("102")));
+        assert!(!orderbook.asks.contains_key(&dec("100")));
+        let level = &orderbook.bids[&dec("101")];
+        assert_eq!(level.len(), 2);
+        assert_eq!(level[0].filled_quantity, dec("1"));
+        assert_eq!(level[1].id, late_bid.id);
+    }
+}
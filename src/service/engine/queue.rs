@@ -0,0 +1,192 @@
+use std::{fmt, sync::{Arc, atomic::{AtomicU64, Ordering}}};
+
+use tokio::sync::mpsc::{self, Receiver, Sender, error::TrySendError};
+
+use crate::service::EngineIx;
+
+//cancels and admin instructions go ahead of new orders. a shutdown goes in the orders lane, behind the orders already sent,
+//while reads still waiting when it is processed get no reply.
+//reads take turns with orders, so a flood of reads can not hold orders back
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lane {
+    Priority,
+    Orders,
+    Reads,
+}
+
+#[derive(Debug)]
+pub enum EngineQueueError {
+    //lane is full, the caller should retry later
+    Busy,
+    //engine has stopped
+    Closed,
+}
+
+//sending side of the engine's instruction lanes, shared by the http handlers and workers
+#[derive(Clone)]
+pub struct EngineSender {
+    priority_tx: Sender<EngineIx>,
+    order_tx: Sender<EngineIx>,
+    read_tx: Sender<EngineIx>,
+    rejected_priority: Arc<AtomicU64>,
+    rejected_orders: Arc<AtomicU64>,
+    rejected_reads: Arc<AtomicU64>
+}
+
+pub struct EngineReceiver {
+    priority_rx: Receiver<EngineIx>,
+    order_rx: Receiver<EngineIx>,
+    read_rx: Receiver<EngineIx>,
+    priority_closed: bool,
+    reads_closed: bool
+}
+
+//each lane holds up to `depth` instructions
+pub fn engine_channel(depth: usize) -> (EngineSender, EngineReceiver) {
+    let (priority_tx, priority_rx) = mpsc::channel::<EngineIx>(depth);
+    let (order_tx, order_rx) = mpsc::channel::<EngineIx>(depth);
+    let (read_tx, read_rx) = mpsc::channel::<EngineIx>(depth);
+
+    let engine_tx = EngineSender {
+        priority_tx: priority_tx,
+        order_tx: order_tx,
+        read_tx: read_tx,
+        rejected_priority: Arc::new(AtomicU64::new(0)),
+        rejected_orders: Arc::new(AtomicU64::new(0)),
+        rejected_reads: Arc::new(AtomicU64::new(0))
+    };
+
+    let engine_rx = EngineReceiver {
+        priority_rx: priority_rx,
+        order_rx: order_rx,
+        read_rx: read_rx,
+        priority_closed: false,
+        reads_closed: false
+    };
+
+    (engine_tx, engine_rx)
+}
+
+impl Lane {
+    pub fn all() -> [Lane; 3] {
+        [Lane::Priority, Lane::Orders, Lane::Reads]
+    }
+
+    pub fn of(ix: &EngineIx) -> Lane {
+        match ix {
+            EngineIx::CancelOrder(_) | EngineIx::SetMarketState { .. } | EngineIx::RegisterUser(_) => {
+                Lane::Priority
+            }
+            EngineIx::L3Snapshot { .. } | EngineIx::GetBalance { .. } | EngineIx::ReconcileState { .. } => {
+                Lane::Reads
+            }
+            _ => {
+                Lane::Orders
+            }
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Lane::Priority => "priority",
+            Lane::Orders => "orders",
+            Lane::Reads => "reads"
+        }
+    }
+}
+
+impl EngineSender {
+    fn lane_tx(&self, lane: Lane) -> &Sender<EngineIx> {
+        match lane {
+            Lane::Priority => &self.priority_tx,
+            Lane::Orders => &self.order_tx,
+            Lane::Reads => &self.read_tx
+        }
+    }
+
+    fn lane_rejected(&self, lane: Lane) -> &AtomicU64 {
+        match lane {
+            Lane::Priority => &self.rejected_priority,
+            Lane::Orders => &self.rejected_orders,
+            Lane::Reads => &self.rejected_reads
+        }
+    }
+
+    //waits for room in the lane, for internal and admin instructions that must not be dropped
+    pub async fn send(&self, ix: EngineIx) -> Result<(), EngineQueueError> {
+        self.lane_tx(Lane::of(&ix)).send(ix).await.map_err(|_| EngineQueueError::Closed)
+    }
+
+    //fails fast when the lane is full, for client requests
+    pub fn try_send(&self, ix: EngineIx) -> Result<(), EngineQueueError> {
+        let lane = Lane::of(&ix);
+
+        match self.lane_tx(lane).try_send(ix) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                self.lane_rejected(lane).fetch_add(1, Ordering::Relaxed);
+                Err(EngineQueueError::Busy)
+            }
+            Err(TrySendError::Closed(_)) => Err(EngineQueueError::Closed)
+        }
+    }
+
+    //instructions waiting in the lane
+    pub fn depth(&self, lane: Lane) -> usize {
+        let tx = self.lane_tx(lane);
+        tx.max_capacity() - tx.capacity()
+    }
+
+    pub fn max_depth(&self, lane: Lane) -> usize {
+        self.lane_tx(lane).max_capacity()
+    }
+
+    //instructions turned away because the lane was full
+    pub fn rejected(&self, lane: Lane) -> u64 {
+        self.lane_rejected(lane).load(Ordering::Relaxed)
+    }
+}
+
+impl EngineReceiver {
+    //priority lane first, then orders and reads. None once the orders lane is closed and drained,
+    //which happens together with the other lanes since all senders live in the same EngineSender
+    pub async fn recv(&mut self) -> Option<EngineIx> {
+        loop {
+            tokio::select! {
+                biased;
+                ix = self.priority_rx.recv(), if !self.priority_closed => {
+                    match ix {
+                        Some(ix) => return Some(ix),
+                        None => self.priority_closed = true
+                    }
+                }
+                (lane, ix) = EngineReceiver::recv_orders_or_reads(&mut self.order_rx, &mut self.read_rx, self.reads_closed) => {
+                    match (lane, ix) {
+                        (_, Some(ix)) => return Some(ix),
+                        (Lane::Reads, None) => self.reads_closed = true,
+                        (_, None) => return None
+                    }
+                }
+            }
+        }
+    }
+
+    //picks at random when both lanes are waiting, so neither can starve the other
+    async fn recv_orders_or_reads(order_rx: &mut Receiver<EngineIx>, read_rx: &mut Receiver<EngineIx>, reads_closed: bool) -> (Lane, Option<EngineIx>) {
+        tokio::select! {
+            ix = order_rx.recv() => (Lane::Orders, ix),
+            ix = read_rx.recv(), if !reads_closed => (Lane::Reads, ix)
+        }
+    }
+}
+
+impl fmt::Display for EngineQueueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineQueueError::Busy => write!(f, "Engine is busy"),
+            EngineQueueError::Closed => write!(f, "Engine is not running")
+        }
+    }
+}
+
+impl std::error::Error for EngineQueueError {}
@@ -0,0 +1,194 @@
+use std::collections::{HashMap, HashSet};
+
+use bigdecimal::{BigDecimal, RoundingMode};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::service::{Order, UserBalance};
+
+//quantities are stored as NUMERIC(38,18), postgres rounds anything finer half away from zero
+const DB_SCALE: i64 = 18;
+
+//a user whose in-memory balance differs from the persisted one, None where a side has no row
+#[derive(Serialize, Clone)]
+pub struct BalanceDrift {
+    pub user_id: Uuid,
+    pub engine: Option<UserBalance>,
+    pub db: Option<UserBalance>,
+    //the same drift as on the previous run with neither side written in between,
+    //so not just a write still queued in a worker
+    pub persistent: bool
+}
+
+//an order resting in the book but not open in the db, or the other way around, or with different fills
+#[derive(Serialize, Clone)]
+pub struct OrderDrift {
+    pub order_id: Uuid,
+    pub engine: Option<Order>,
+    pub db: Option<Order>,
+    pub persistent: bool
+}
+
+//balances and resting orders as the engine holds them, copied on the engine thread
+pub struct EngineState {
+    pub balances: Vec<UserBalance>,
+    pub orders: Vec<Order>
+}
+
+#[derive(Serialize, Clone)]
+pub struct ReconcileReport {
+    pub market: String,
+    pub checked_at: i64,
+    pub users_checked: usize,
+    pub orders_checked: usize,
+    pub balance_drifts: Vec<BalanceDrift>,
+    pub order_drifts: Vec<OrderDrift>
+}
+
+//remembers what drifted on the last run to tell persistent drift from writes in flight
+pub struct Reconciler {
+    //user -> (engine, db) balances of the last drift
+    drifted_users: HashMap<Uuid, (Option<UserBalance>, Option<UserBalance>)>,
+    //order -> (engine, db) orders of the last drift
+    drifted_orders: HashMap<Uuid, (Option<Order>, Option<Order>)>
+}
+
+impl ReconcileReport {
+    pub fn is_clean(&self) -> bool {
+        self.balance_drifts.is_empty() && self.order_drifts.is_empty()
+    }
+}
+
+impl Reconciler {
+    pub fn default() -> Self {
+        Self {
+            drifted_users: HashMap::new(),
+            drifted_orders: HashMap::new()
+        }
+    }
+
+    pub fn reconcile(
+        &mut self,
+        market: &str,
+        now: i64,
+        engine_state: EngineState,
+        db_balances: Vec<UserBalance>,
+        db_orders: Vec<Order>
+    ) -> ReconcileReport {
+        let mut engine_balances: HashMap<Uuid, UserBalance> = engine_state.balances.into_iter()
+            .map(|balance| (balance.user_id, balance))
+            .collect();
+        let mut db_balances: HashMap<Uuid, UserBalance> = db_balances.into_iter()
+            .map(|balance| (balance.user_id, balance))
+            .collect();
+
+        let mut user_ids: HashSet<Uuid> = engine_balances.keys().copied().collect();
+        user_ids.extend(db_balances.keys().copied());
+
+        let mut balance_drifts: Vec<BalanceDrift> = Vec::new();
+        for user_id in user_ids.iter() {
+            let engine = engine_balances.remove(user_id);
+            let db = db_balances.remove(user_id);
+
+            if !Reconciler::same_balance(&engine, &db) {
+                let persistent = self.drifted_users.get(user_id).is_some_and(|(last_engine, last_db)| {
+                    Reconciler::same_balance(last_engine, &engine) && Reconciler::same_balance(last_db, &db)
+                });
+
+                balance_drifts.push(BalanceDrift {
+                    user_id: *user_id,
+                    engine: engine,
+                    db: db,
+                    persistent: persistent
+                });
+            }
+        }
+
+        let mut engine_orders: HashMap<Uuid, Order> = engine_state.orders.into_iter()
+            .map(|order| (order.id, order))
+            .collect();
+        let mut db_orders: HashMap<Uuid, Order> = db_orders.into_iter()
+            .map(|order| (order.id, order))
+            .collect();
+
+        let mut order_ids: HashSet<Uuid> = engine_orders.keys().copied().collect();
+        order_ids.extend(db_orders.keys().copied());
+
+        let mut order_drifts: Vec<OrderDrift> = Vec::new();
+        for order_id in order_ids.iter() {
+            let engine = engine_orders.remove(order_id);
+            let db = db_orders.remove(order_id);
+
+            if !Reconciler::same_order(&engine, &db) {
+                let persistent = self.drifted_orders.get(order_id).is_some_and(|(last_engine, last_db)| {
+                    Reconciler::same_order(last_engine, &engine) && Reconciler::same_order(last_db, &db)
+                });
+
+                order_drifts.push(OrderDrift {
+                    order_id: *order_id,
+                    engine: engine,
+                    db: db,
+                    persistent: persistent
+                });
+            }
+        }
+
+        self.drifted_users = balance_drifts.iter()
+            .map(|drift| (drift.user_id, (drift.engine.clone(), drift.db.clone())))
+            .collect();
+        self.drifted_orders = order_drifts.iter()
+            .map(|drift| (drift.order_id, (drift.engine.clone(), drift.db.clone())))
+            .collect();
+
+        ReconcileReport {
+            market: market.to_string(),
+            checked_at: now,
+            users_checked: user_ids.len(),
+            orders_checked: order_ids.len(),
+            balance_drifts: balance_drifts,
+            order_drifts: order_drifts
+        }
+    }
+
+    //the engine keeps exact products, compare them as they would be stored
+    fn same_qty(a: &BigDecimal, b: &BigDecimal) -> bool {
+        a.with_scale_round(DB_SCALE, RoundingMode::HalfUp) == b.with_scale_round(DB_SCALE, RoundingMode::HalfUp)
+    }
+
+    //equal as stored, two missing balances are the same
+    fn same_balance(a: &Option<UserBalance>, b: &Option<UserBalance>) -> bool {
+        match (a, b) {
+            (Some(a), Some(b)) => {
+                Reconciler::same_qty(&a.free_base_qty, &b.free_base_qty)
+                    && Reconciler::same_qty(&a.free_quote_qty, &b.free_quote_qty)
+                    && Reconciler::same_qty(&a.locked_base_qty, &b.locked_base_qty)
+                    && Reconciler::same_qty(&a.locked_quote_qty, &b.locked_quote_qty)
+            }
+            (None, None) => {
+                true
+            }
+            _ => {
+                false
+            }
+        }
+    }
+
+    fn same_order(a: &Option<Order>, b: &Option<Order>) -> bool {
+        match (a, b) {
+            (Some(a), Some(b)) => {
+                a.user_id == b.user_id
+                    && a.side == b.side
+                    && Reconciler::same_qty(&a.price, &b.price)
+                    && Reconciler::same_qty(&a.quantity, &b.quantity)
+                    && Reconciler::same_qty(&a.filled_quantity, &b.filled_quantity)
+                    && a.status == b.status
+            }
+            (None, None) => {
+                true
+            }
+            _ => {
+                false
+            }
+        }
+    }
+}
@@ -2,6 +2,8 @@ use bigdecimal::BigDecimal;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::service::Side;
+
 
 #[derive(sqlx::FromRow, Serialize, Deserialize, Clone)]
 pub struct Trade {
@@ -10,5 +12,13 @@ pub struct Trade {
     pub sell_order_id: Uuid,
     pub price: BigDecimal,
     pub quantity: BigDecimal,
+    pub buyer_fee: BigDecimal,
+    pub seller_fee: BigDecimal,
+    pub market: String,
+    pub buyer_user_id: Uuid,
+    pub seller_user_id: Uuid,
+    pub maker_order_id: Uuid,
+    pub taker_side: Side,
+    pub seq: i64,
     pub created_at: i64
 }
\ No newline at end of file
@@ -0,0 +1,260 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}};
+
+use bigdecimal::BigDecimal;
+use serde::Serialize;
+use tokio::sync::{broadcast::{self, error::RecvError}, mpsc::{self, error::SendError}};
+use uuid::Uuid;
+
+use crate::service::{BalanceEvent, InsertTradeArgs, Order, OrderEvent, Side, Status, TradeEvent, UserBalance};
+
+//events of its own user a slow private websocket may fall behind by before it misses some
+pub const USER_EVENT_CAPACITY: usize = 1024;
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderUpdateKind {
+    //in the book without fills
+    Accepted,
+    PartiallyFilled,
+    //closed, market orders close once they matched all they could
+    Filled,
+    Cancelled,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Liquidity {
+    Maker,
+    Taker,
+}
+
+#[derive(Serialize, Clone)]
+pub struct OrderUpdate {
+    pub kind: OrderUpdateKind,
+    pub order: Order
+}
+
+//one side of a trade, as seen by the user on that side
+#[derive(Serialize, Clone)]
+pub struct Fill {
+    pub user_id: Uuid,
+    pub order_id: Uuid,
+    pub market: String,
+    pub seq: i64,
+    pub side: Side,
+    pub price: BigDecimal,
+    pub quantity: BigDecimal,
+    pub fee: BigDecimal,
+    pub liquidity: Liquidity
+}
+
+//an order or cancel of the user that the engine did not execute, nothing was persisted for it
+#[derive(Serialize, Clone)]
+pub struct Rejection {
+    pub user_id: Uuid,
+    //the order created or cancelled
+    pub order_id: Uuid,
+    pub reason: String
+}
+
+//private updates pushed to the owning user's websocket
+#[derive(Serialize, Clone)]
+#[serde(tag = "channel", content = "data", rename_all = "snake_case")]
+pub enum UserEvent {
+    Order(OrderUpdate),
+    Fill(Fill),
+    Balance(UserBalance),
+    Rejected(Rejection),
+}
+
+//worker events that users are told about
+pub trait ToUserEvents {
+    fn user_events(&self) -> Vec<UserEvent>;
+}
+
+//one channel per user with a private websocket open, so a session only receives its own user's
+//events and only falls behind because of them. events of users without a session are dropped
+#[derive(Clone)]
+pub struct UserFeed {
+    channels: Arc<Mutex<HashMap<Uuid, broadcast::Sender<UserEvent>>>>
+}
+
+//a session's end of its user's channel, the channel is removed when the user's last session drops
+pub struct UserFeedReceiver {
+    user_id: Uuid,
+    rx: Option<broadcast::Receiver<UserEvent>>,
+    feed: UserFeed
+}
+
+//channel to a persistence worker that also feeds every event to connected users,
+//so users see exactly what is being written
+pub struct UserFeedSender<E> {
+    tx: mpsc::Sender<E>,
+    feed: UserFeed
+}
+
+impl UserEvent {
+    pub fn user_id(&self) -> Uuid {
+        match self {
+            UserEvent::Order(update) => update.order.user_id,
+            UserEvent::Fill(fill) => fill.user_id,
+            UserEvent::Balance(balance) => balance.user_id,
+            UserEvent::Rejected(rejection) => rejection.user_id
+        }
+    }
+}
+
+impl OrderUpdateKind {
+    pub fn of(order: &Order) -> Self {
+        match order.status {
+            Status::Cancelled => {
+                OrderUpdateKind::Cancelled
+            }
+            Status::Close => {
+                OrderUpdateKind::Filled
+            }
+            Status::Open => {
+                if order.filled_quantity > BigDecimal::from(0) {
+                    OrderUpdateKind::PartiallyFilled
+                } else {
+                    OrderUpdateKind::Accepted
+                }
+            }
+        }
+    }
+}
+
+impl ToUserEvents for OrderEvent {
+    fn user_events(&self) -> Vec<UserEvent> {
+        match self {
+            OrderEvent::UpdateOrder(order) => {
+                vec![UserEvent::Order(OrderUpdate { kind: OrderUpdateKind::of(order), order: order.clone() })]
+            }
+        }
+    }
+}
+
+impl ToUserEvents for BalanceEvent {
+    fn user_events(&self) -> Vec<UserEvent> {
+        match self {
+            BalanceEvent::UpdateBalance(args) => {
+                vec![UserEvent::Balance(args.balance.clone())]
+            }
+        }
+    }
+}
+
+impl ToUserEvents for TradeEvent {
+    fn user_events(&self) -> Vec<UserEvent> {
+        match self {
+            TradeEvent::InsertTrade(args) => {
+                vec![
+                    UserEvent::Fill(Fill::of(args, Side::Bid)),
+                    UserEvent::Fill(Fill::of(args, Side::Ask))
+                ]
+            }
+        }
+    }
+}
+
+impl Fill {
+    //the buyer's fill for Bid, the seller's for Ask
+    fn of(args: &InsertTradeArgs, side: Side) -> Self {
+        let (user_id, order_id, fee) = match side {
+            Side::Bid => (args.buyer_user_id, args.buy_order_id, args.buyer_fee.clone()),
+            Side::Ask => (args.seller_user_id, args.sell_order_id, args.seller_fee.clone())
+        };
+
+        let liquidity = if order_id == args.maker_order_id {
+            Liquidity::Maker
+        } else {
+            Liquidity::Taker
+        };
+
+        Self {
+            user_id: user_id,
+            order_id: order_id,
+            market: args.market.clone(),
+            seq: args.seq,
+            side: side,
+            price: args.price.clone(),
+            quantity: args.quantity.clone(),
+            fee: fee,
+            liquidity: liquidity
+        }
+    }
+}
+
+impl UserFeed {
+    pub fn default() -> Self {
+        Self {
+            channels: Arc::new(Mutex::new(HashMap::new()))
+        }
+    }
+
+    //the user's first session creates the channel
+    pub fn subscribe(&self, user_id: Uuid) -> UserFeedReceiver {
+        let rx = self.channels.lock().unwrap()
+            .entry(user_id)
+            .or_insert_with(|| broadcast::channel::<UserEvent>(USER_EVENT_CAPACITY).0)
+            .subscribe();
+
+        UserFeedReceiver {
+            user_id: user_id,
+            rx: Some(rx),
+            feed: self.clone()
+        }
+    }
+
+    pub fn publish(&self, event: UserEvent) {
+        if let Some(tx) = self.channels.lock().unwrap().get(&event.user_id()) {
+            let _ = tx.send(event);
+        }
+    }
+
+    fn remove_if_unused(&self, user_id: Uuid) {
+        let mut channels = self.channels.lock().unwrap();
+        if channels.get(&user_id).is_some_and(|tx| tx.receiver_count() == 0) {
+            channels.remove(&user_id);
+        }
+    }
+}
+
+impl UserFeedReceiver {
+    pub async fn recv(&mut self) -> Result<UserEvent, RecvError> {
+        match self.rx.as_mut() {
+            Some(rx) => rx.recv().await,
+            None => Err(RecvError::Closed)
+        }
+    }
+}
+
+impl Drop for UserFeedReceiver {
+    fn drop(&mut self) {
+        //the receiver has to be gone before the count is checked
+        self.rx.take();
+        self.feed.remove_if_unused(self.user_id);
+    }
+}
+
+impl<E: ToUserEvents> UserFeedSender<E> {
+    pub fn default(tx: mpsc::Sender<E>, feed: UserFeed) -> Self {
+        Self {
+            tx: tx,
+            feed: feed
+        }
+    }
+
+    pub async fn send(&self, event: E) -> Result<(), SendError<E>> {
+        for user_event in event.user_events().into_iter() {
+            self.feed.publish(user_event);
+        }
+
+        self.tx.send(event).await
+    }
+
+    //for events users are told about that no worker writes
+    pub fn publish(&self, user_event: UserEvent) {
+        self.feed.publish(user_event);
+    }
+}
@@ -10,5 +10,14 @@ pub use trade_worker::*;
 pub mod order_worker;
 pub use order_worker::*;
 
+pub mod supervisor;
+pub use supervisor::*;
+
+pub mod volume_worker;
+pub use volume_worker::*;
+
+pub mod reconcile_worker;
+pub use reconcile_worker::*;
+
 pub mod ws;
 pub use ws::*;
\ No newline at end of file
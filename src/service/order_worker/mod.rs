@@ -1,36 +1,57 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
 use sqlx::{Pool, Postgres};
-use tokio::sync::mpsc::Receiver;
 
-use crate::{db::{update_order}, service::{Order}};
+use uuid::Uuid;
+
+use crate::{db::{update_order, update_orders}, service::{Order, Worker}};
 
 
 pub struct OrderWorker {
-    pool: Pool<Postgres>,
-    balance_rx: Receiver<OrderEvent>
+    pool: Pool<Postgres>
 }
 
 impl OrderWorker {
-    pub fn default(pool: Pool<Postgres>, balance_rx: Receiver<OrderEvent>) -> Self {
+    pub fn default(pool: Pool<Postgres>) -> Self {
         Self { 
-            pool: pool, 
-            balance_rx: balance_rx 
+            pool: pool
         }
     }
+}
 
-    pub async fn run(&mut self) {
-        loop {
-            if let Some(cmd) = self.balance_rx.recv().await {
-                match cmd {
-                    OrderEvent::UpdateOrder(args) => {
-                        update_order(&self.pool, args).await.unwrap()
-                    }
+impl Worker for OrderWorker {
+    type Event = OrderEvent;
+
+    const NAME: &'static str = "order";
+
+    async fn handle(&mut self, event: &OrderEvent) -> anyhow::Result<()> {
+        match event {
+            OrderEvent::UpdateOrder(args) => {
+                update_order(&self.pool, args.clone()).await
+            }
+        }
+    }
+
+    //every update carries the full order state, so only the latest one per order is written
+    async fn handle_batch(&mut self, events: &[OrderEvent]) -> anyhow::Result<()> {
+        let mut latest: HashMap<Uuid, Order> = HashMap::new();
+        for event in events.iter() {
+            match event {
+                OrderEvent::UpdateOrder(args) => {
+                    latest.insert(args.id, args.clone());
                 }
             }
         }
+
+        let orders: Vec<Order> = latest.into_values().collect();
+        update_orders(&self.pool, &orders).await?;
+        Ok(())
     }
 }
 
 
+#[derive(Serialize, Clone)]
 pub enum OrderEvent {
     UpdateOrder(Order)
-}
\ No newline at end of file
+}
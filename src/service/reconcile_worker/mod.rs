@@ -0,0 +1,116 @@
+use std::{collections::HashSet, time::Duration};
+
+use chrono::Utc;
+use sqlx::{Pool, Postgres};
+use tokio::{sync::{mpsc, oneshot}, time::Instant};
+use uuid::Uuid;
+
+use crate::{db::{get_all_user_balance, get_open_orders}, service::{EngineIx, EngineSender, ReconcileReport, Reconciler}};
+
+//how often in-memory state is checked against postgres
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+//admin request for an immediate run, the reply is dropped for unknown markets or failed runs
+pub struct ReconcileRequest {
+    pub market: String,
+    pub reply: oneshot::Sender<ReconcileReport>
+}
+
+//reads postgres here and only asks the engine for a copy of its state,
+//so the engine thread never waits on the database
+pub struct ReconcileWorker {
+    pool: Pool<Postgres>,
+    market: String,
+    engine_tx: EngineSender,
+    request_rx: mpsc::Receiver<ReconcileRequest>,
+    reconciler: Reconciler
+}
+
+impl ReconcileWorker {
+    pub fn default(pool: Pool<Postgres>, market: String, engine_tx: EngineSender, request_rx: mpsc::Receiver<ReconcileRequest>) -> Self {
+        Self {
+            pool: pool,
+            market: market,
+            engine_tx: engine_tx,
+            request_rx: request_rx,
+            reconciler: Reconciler::default()
+        }
+    }
+
+    pub async fn run(&mut self) {
+        let mut interval = tokio::time::interval_at(Instant::now() + RECONCILE_INTERVAL, RECONCILE_INTERVAL);
+
+        loop {
+            let reply = tokio::select! {
+                _ = interval.tick() => {
+                    None
+                }
+                request = self.request_rx.recv() => {
+                    let Some(request) = request else {
+                        return;
+                    };
+
+                    if request.market != self.market {
+                        eprintln!("Unknown market {}", request.market);
+                        continue;
+                    }
+                    Some(request.reply)
+                }
+            };
+
+            match self.reconcile().await {
+                Ok(report) => {
+                    if let Some(reply) = reply {
+                        let _ = reply.send(report);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Reconciliation failed: {}", e);
+                }
+            }
+        }
+    }
+
+    //compare balances and resting orders with what the workers persisted, alerting on drift
+    //that is unchanged on both sides since the last run. drift seen once may just be writes still queued in a worker
+    async fn reconcile(&mut self) -> anyhow::Result<ReconcileReport> {
+        //postgres is read before the engine, so a write it lacks is one the engine already made
+        let db_balances = get_all_user_balance(&self.pool).await?;
+        let db_orders = get_open_orders(&self.pool, &self.market).await?;
+
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.engine_tx.send(EngineIx::ReconcileState { market: self.market.clone(), reply: reply_tx }).await?;
+        let mut engine_state = reply_rx.await?;
+
+        //users whose registration never reached the engine have nothing in memory yet, their row is their balance
+        let engine_users: HashSet<Uuid> = engine_state.balances.iter().map(|balance| balance.user_id).collect();
+        for db_balance in db_balances.iter() {
+            if !engine_users.contains(&db_balance.user_id) {
+                println!("Registering user {} missing from market {}", db_balance.user_id, self.market);
+                self.engine_tx.send(EngineIx::RegisterUser(db_balance.clone())).await?;
+                engine_state.balances.push(db_balance.clone());
+            }
+        }
+
+        let report = self.reconciler.reconcile(
+            &self.market,
+            Utc::now().timestamp_millis(),
+            engine_state,
+            db_balances,
+            db_orders
+        );
+
+        for drift in report.balance_drifts.iter().filter(|drift| drift.persistent) {
+            eprintln!("ALERT: market {} balance of user {} differs between engine and db", self.market, drift.user_id);
+        }
+        for drift in report.order_drifts.iter().filter(|drift| drift.persistent) {
+            eprintln!("ALERT: market {} order {} differs between engine and db", self.market, drift.order_id);
+        }
+
+        if report.is_clean() {
+            println!("Market {} reconciled {} users and {} orders", self.market, report.users_checked, report.orders_checked);
+        }
+
+        Ok(report)
+    }
+}
@@ -0,0 +1,194 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use tokio::{sync::{Mutex, mpsc::Receiver}, time::Instant};
+
+use crate::db::create_dead_letter;
+
+//transient failures are retried this many times before the event is dead-lettered
+const MAX_RETRIES: i32 = 8;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+//a batch is written once it holds MAX_BATCH_SIZE events or BATCH_WINDOW after its first event
+const MAX_BATCH_SIZE: usize = 500;
+const BATCH_WINDOW: Duration = Duration::from_millis(10);
+
+//pause before restarting a crashed worker
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+//persists one kind of engine event
+pub trait Worker: Send + 'static {
+    type Event: Serialize + Clone + Send + Sync + 'static;
+
+    const NAME: &'static str;
+
+    fn handle(&mut self, event: &Self::Event) -> impl Future<Output = anyhow::Result<()>> + Send;
+
+    //persist events in as few round-trips as possible. if the batch keeps failing it is
+    //retried event by event through `handle`, so one bad event does not sink the rest
+    fn handle_batch(&mut self, events: &[Self::Event]) -> impl Future<Output = anyhow::Result<()>> + Send;
+}
+
+//drives a worker, retrying transient db errors, dead-lettering events that keep failing and
+//restarting the worker when it panics. the receiver outlives the worker so queued events survive
+//a restart, the batch being handled during a crash is dead-lettered as the likely cause
+pub struct Supervisor<W: Worker> {
+    pool: Pool<Postgres>,
+    event_rx: Arc<Mutex<Receiver<W::Event>>>,
+    in_flight: Arc<std::sync::Mutex<Vec<W::Event>>>,
+    new_worker: Box<dyn Fn() -> W + Send>
+}
+
+impl<W: Worker> Supervisor<W> {
+    pub fn default(pool: Pool<Postgres>, event_rx: Receiver<W::Event>, new_worker: impl Fn() -> W + Send + 'static) -> Self {
+        Self {
+            pool: pool,
+            event_rx: Arc::new(Mutex::new(event_rx)),
+            in_flight: Arc::new(std::sync::Mutex::new(Vec::new())),
+            new_worker: Box::new(new_worker)
+        }
+    }
+
+    //returns once the event channel is closed and drained
+    pub async fn run(&mut self) {
+        loop {
+            let worker = (self.new_worker)();
+            let task = tokio::spawn(Supervisor::drive(worker, self.pool.clone(), self.event_rx.clone(), self.in_flight.clone()));
+
+            match task.await {
+                Ok(()) => {
+                    return;
+                }
+                Err(e) => {
+                    eprintln!("{} worker crashed: {}, restarting", W::NAME, e);
+                }
+            }
+
+            let crashed_events = std::mem::take(&mut *self.in_flight.lock().unwrap());
+            for event in crashed_events.iter() {
+                dead_letter::<W>(&self.pool, event, "worker crashed while handling event", 1).await;
+            }
+
+            tokio::time::sleep(RESTART_DELAY).await;
+        }
+    }
+
+    async fn drive(mut worker: W, pool: Pool<Postgres>, event_rx: Arc<Mutex<Receiver<W::Event>>>, in_flight: Arc<std::sync::Mutex<Vec<W::Event>>>) {
+        let mut batch: Vec<W::Event> = Vec::with_capacity(MAX_BATCH_SIZE);
+
+        loop {
+            if !next_batch(&mut *event_rx.lock().await, &mut batch).await {
+                return;
+            }
+
+            in_flight.lock().unwrap().clone_from(&batch);
+            process_batch(&mut worker, &pool, &batch).await;
+            in_flight.lock().unwrap().clear();
+
+            batch.clear();
+        }
+    }
+}
+
+//wait for one event, then keep collecting until the batch is full or the window closes.
+//false once the channel is closed and drained
+async fn next_batch<E>(event_rx: &mut Receiver<E>, batch: &mut Vec<E>) -> bool {
+    if event_rx.recv_many(batch, MAX_BATCH_SIZE).await == 0 {
+        return false;
+    }
+
+    let deadline = Instant::now() + BATCH_WINDOW;
+    while batch.len() < MAX_BATCH_SIZE {
+        let limit = MAX_BATCH_SIZE - batch.len();
+        match tokio::time::timeout_at(deadline, event_rx.recv_many(batch, limit)).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+    }
+
+    true
+}
+
+async fn process_batch<W: Worker>(worker: &mut W, pool: &Pool<Postgres>, events: &[W::Event]) {
+    let mut attempts = 0;
+    let mut backoff = INITIAL_BACKOFF;
+
+    loop {
+        attempts += 1;
+
+        let Err(e) = worker.handle_batch(events).await else {
+            return;
+        };
+
+        if !is_transient(&e) || attempts > MAX_RETRIES {
+            eprintln!("{} worker batch of {} failed after {} attempts: {}, retrying one by one", W::NAME, events.len(), attempts, e);
+            break;
+        }
+
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+
+    for event in events.iter() {
+        process(worker, pool, event).await;
+    }
+}
+
+async fn process<W: Worker>(worker: &mut W, pool: &Pool<Postgres>, event: &W::Event) {
+    let mut attempts = 0;
+    let mut backoff = INITIAL_BACKOFF;
+
+    loop {
+        attempts += 1;
+
+        let Err(e) = worker.handle(event).await else {
+            return;
+        };
+
+        if !is_transient(&e) || attempts > MAX_RETRIES {
+            eprintln!("{} worker failed after {} attempts: {}", W::NAME, attempts, e);
+            dead_letter::<W>(pool, event, &e.to_string(), attempts).await;
+            return;
+        }
+
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+//keeps trying until stored, an event is never dropped because the db is unavailable
+async fn dead_letter<W: Worker>(pool: &Pool<Postgres>, event: &W::Event, error: &str, attempts: i32) {
+    let payload = match serde_json::to_value(event) {
+        Ok(payload) => payload,
+        Err(e) => serde_json::Value::String(format!("unserializable event: {}", e))
+    };
+
+    let mut backoff = INITIAL_BACKOFF;
+    while let Err(e) = create_dead_letter(pool, W::NAME, payload.clone(), error, attempts).await {
+        eprintln!("Failed to dead-letter {} event: {}", W::NAME, e);
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+//connection loss, pool exhaustion, serialization failures and deadlocks may succeed on retry
+pub fn is_transient(e: &anyhow::Error) -> bool {
+    let Some(e) = e.downcast_ref::<sqlx::Error>() else {
+        return false;
+    };
+
+    match e {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut | sqlx::Error::Tls(_) => {
+            true
+        }
+        sqlx::Error::Database(db_error) => {
+            let code = db_error.code().unwrap_or_default();
+            code.starts_with("08") || code.starts_with("53") || code.starts_with("57P") || code == "40001" || code == "40P01"
+        }
+        _ => {
+            false
+        }
+    }
+}
@@ -1,45 +1,69 @@
 use bigdecimal::BigDecimal;
 use serde::{Deserialize, Serialize};
 use sqlx::{Pool, Postgres};
-use tokio::sync::mpsc::Receiver;
 use uuid::Uuid;
 
-use crate::db::create_trade;
+use crate::{db::{create_trade, create_trades}, service::{Side, Worker}};
 
 pub struct TradeWorker {
-    pool: Pool<Postgres>,
-    trade_rx: Receiver<TradeEvent>
+    pool: Pool<Postgres>
 }
 
 impl TradeWorker {
-    pub fn default(pool: Pool<Postgres>, trade_rx: Receiver<TradeEvent>) -> Self {
+    pub fn default(pool: Pool<Postgres>) -> Self {
         Self { 
-            pool: pool, 
-            trade_rx: trade_rx
+            pool: pool
         }
     }
+}
+
+impl Worker for TradeWorker {
+    type Event = TradeEvent;
+
+    const NAME: &'static str = "trade";
 
-    pub async fn run(&mut self) {
-        loop {
-            if let Some(cmd) = self.trade_rx.recv().await {
-                match cmd {
-                    TradeEvent::InsertTrade(args) => {
-                        create_trade(&self.pool, args).await.unwrap();
-                    }
-                }
+    async fn handle(&mut self, event: &TradeEvent) -> anyhow::Result<()> {
+        match event {
+            TradeEvent::InsertTrade(args) => {
+                create_trade(&self.pool, args.clone()).await?;
+                Ok(())
             }
         }
     }
+
+    async fn handle_batch(&mut self, events: &[TradeEvent]) -> anyhow::Result<()> {
+        let trades: Vec<InsertTradeArgs> = events.iter()
+            .map(|event| match event {
+                TradeEvent::InsertTrade(args) => args.clone()
+            })
+            .collect();
+
+        create_trades(&self.pool, &trades).await?;
+        Ok(())
+    }
 }
 
+#[derive(Serialize, Clone)]
 pub enum TradeEvent {
     InsertTrade(InsertTradeArgs)
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct InsertTradeArgs {
+    //assigned by the engine, so balance changes can refer to the trade before it is written
+    pub id: Uuid,
     pub buy_order_id: Uuid,
     pub sell_order_id: Uuid,
     pub price: BigDecimal,
-    pub quantity: BigDecimal
+    pub quantity: BigDecimal,
+    pub buyer_fee: BigDecimal,
+    pub seller_fee: BigDecimal,
+    pub market: String,
+    pub buyer_user_id: Uuid,
+    pub seller_user_id: Uuid,
+    pub maker_order_id: Uuid,
+    pub taker_side: Side,
+    pub seq: i64,
+    //engine time in milliseconds, candles are bucketed on it
+    pub created_at: i64
 }
\ No newline at end of file
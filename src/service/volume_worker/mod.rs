@@ -0,0 +1,47 @@
+use std::time::Duration;
+
+use sqlx::{Pool, Postgres};
+
+use crate::{db::{get_fee_schedule, refresh_user_trading_volume}, service::{EngineIx, EngineSender}};
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+pub struct VolumeWorker {
+    pool: Pool<Postgres>,
+    market: String,
+    engine_tx: EngineSender
+}
+
+impl VolumeWorker {
+    pub fn default(pool: Pool<Postgres>, market: String, engine_tx: EngineSender) -> Self {
+        Self {
+            pool: pool,
+            market: market,
+            engine_tx: engine_tx
+        }
+    }
+
+    //first tick fires immediately, so tiers are also refreshed on startup
+    pub async fn run(&mut self) {
+        let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = self.refresh_fee_tiers().await {
+                eprintln!("Failed to refresh fee tiers: {}", e);
+            }
+        }
+    }
+
+    async fn refresh_fee_tiers(&mut self) -> anyhow::Result<()> {
+        //recompute 30 day volumes and tiers
+        refresh_user_trading_volume(&self.pool, &self.market).await?;
+
+        //push the reloaded schedule, with new user tiers, into the engine
+        let fee_schedule = get_fee_schedule(&self.pool, &self.market).await?;
+        self.engine_tx.send(EngineIx::UpdateFeeSchedule(fee_schedule)).await?;
+
+        Ok(())
+    }
+}
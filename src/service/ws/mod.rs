@@ -0,0 +1,319 @@
+//websocket market data.
+//
+//clients send json requests:
+//  {"op": "subscribe", "channel": "depth", "market": "BTC_USDC"}
+//  {"op": "subscribe", "channel": "kline", "market": "BTC_USDC", "interval": "1m"}
+//  {"op": "unsubscribe", "channel": "depth", "market": "BTC_USDC"}
+//each request is answered with {"event": "subscribed" | "unsubscribed", ...the subscription}
+//or {"event": "error", "message": ...}.
+//
+//updates for subscribed channels are pushed as {"channel": ..., "data": {...}}:
+//  depth   changed price levels after an instruction, quantity 0 removes the level
+//  trade   every trade as it happens
+//  ticker  the 24h ticker whenever it changes
+//  kline   the whole open candle whenever a trade changes it
+//  l3      every add, execute and cancel of resting orders, only when the server runs with L3_FEED=true
+//  auction the indicative uncrossing price and volume whenever it changes while the market is PreOpen,
+//          sent once on subscribing when an auction is running
+//
+//keeping a local book in sync:
+//  depth and l3 updates carry the book sequence `seq`, and `prev_seq`, the seq of the previous update on
+//  the same channel. both channels share the sequence, an instruction changing the book gives both the same seq.
+//  subscribing to depth sends a {"channel": "depth_snapshot", "data": {"seq": ..., "bids": [...], "asks": [...]}}
+//  with the top SNAPSHOT_DEPTH levels right after the subscribed event, l3 sends an l3_snapshot of every order.
+//  1. discard updates with seq <= the snapshot seq, they are already in it
+//  2. the first update applied has prev_seq <= the snapshot seq, every later one has prev_seq equal to
+//     the seq of the last one applied
+//  3. on a gap, unsubscribe and subscribe again (or for depth fetch GET /depth?limit=500 and go back to 1)
+//  the server sends new snapshots itself when a client falls too far behind, which restarts at 1.
+//  the sequence restarts when the server does, which drops every connection, so reconnects always resync
+use std::collections::HashSet;
+
+use actix_ws::{AggregatedMessage, AggregatedMessageStream, Session};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast::{self, error::RecvError}, oneshot, watch};
+
+use crate::service::{CandleInterval, DepthSnapshot, EngineIx, EngineSender, L3Snapshot, MarketEvent, MarketSnapshot, SNAPSHOT_DEPTH};
+
+pub mod private;
+pub use private::*;
+
+//largest websocket frame or continuation accepted from a client
+const MAX_FRAME_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+#[serde(tag = "channel", rename_all = "snake_case")]
+pub enum Subscription {
+    Depth { market: String },
+    Trade { market: String },
+    Ticker { market: String },
+    Kline { market: String, interval: CandleInterval },
+    L3 { market: String },
+    Auction { market: String },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum ClientRequest {
+    Subscribe(Subscription),
+    Unsubscribe(Subscription),
+}
+
+//sent to one session only, framed like MarketEvent
+#[derive(Serialize)]
+#[serde(tag = "channel", content = "data", rename_all = "snake_case")]
+pub enum SessionEvent {
+    DepthSnapshot(DepthSnapshot),
+    L3Snapshot(L3Snapshot),
+}
+
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ServerResponse {
+    Subscribed(Subscription),
+    Unsubscribed(Subscription),
+    Error { message: String },
+}
+
+pub struct WsSession {
+    market: String,
+    session: Session,
+    snapshot: watch::Receiver<MarketSnapshot>,
+    engine_tx: EngineSender,
+    subscriptions: HashSet<Subscription>
+}
+
+impl Subscription {
+    pub fn market(&self) -> &str {
+        match self {
+            Subscription::Depth { market } | Subscription::Trade { market } | Subscription::Ticker { market } | Subscription::L3 { market } | Subscription::Auction { market } => {
+                market
+            }
+            Subscription::Kline { market, .. } => {
+                market
+            }
+        }
+    }
+}
+
+impl MarketEvent {
+    //subscription that receives this event
+    pub fn subscription(&self) -> Subscription {
+        match self {
+            MarketEvent::Depth(update) => {
+                Subscription::Depth { market: update.market.clone() }
+            }
+            MarketEvent::Trade(trade) => {
+                Subscription::Trade { market: trade.market.clone() }
+            }
+            MarketEvent::Ticker(ticker) => {
+                Subscription::Ticker { market: ticker.market.clone() }
+            }
+            MarketEvent::Kline(update) => {
+                Subscription::Kline { market: update.market.clone(), interval: update.interval }
+            }
+            MarketEvent::L3(update) => {
+                Subscription::L3 { market: update.market.clone() }
+            }
+            MarketEvent::Auction(auction) => {
+                Subscription::Auction { market: auction.market.clone() }
+            }
+        }
+    }
+}
+
+impl WsSession {
+    pub fn default(market: String, session: Session, snapshot: watch::Receiver<MarketSnapshot>, engine_tx: EngineSender) -> Self {
+        Self {
+            market: market,
+            session: session,
+            snapshot: snapshot,
+            engine_tx: engine_tx,
+            subscriptions: HashSet::new()
+        }
+    }
+
+    //runs until the client disconnects or the engine stops publishing
+    pub async fn run(mut self, stream: actix_ws::MessageStream, mut events: broadcast::Receiver<MarketEvent>) {
+        let mut stream: AggregatedMessageStream = stream
+            .max_frame_size(MAX_FRAME_SIZE)
+            .aggregate_continuations()
+            .max_continuation_size(MAX_FRAME_SIZE);
+
+        loop {
+            tokio::select! {
+                msg = stream.recv() => {
+                    let Some(Ok(msg)) = msg else {
+                        break;
+                    };
+
+                    let open = match msg {
+                        AggregatedMessage::Text(text) => {
+                            self.handle_request(&text).await
+                        }
+                        AggregatedMessage::Ping(bytes) => {
+                            self.session.pong(&bytes).await.is_ok()
+                        }
+                        AggregatedMessage::Close(_) => {
+                            false
+                        }
+                        _ => {
+                            true
+                        }
+                    };
+
+                    if !open {
+                        break;
+                    }
+                }
+                event = events.recv() => {
+                    let open = match event {
+                        Ok(event) => {
+                            self.handle_event(&event).await
+                        }
+                        //the client fell behind, it keeps receiving from the newest event
+                        //and book subscribers start over from fresh snapshots
+                        Err(RecvError::Lagged(missed)) => {
+                            self.send(&ServerResponse::Error { message: format!("Missed {} updates", missed) }).await
+                                && self.resync_book().await
+                        }
+                        Err(RecvError::Closed) => {
+                            false
+                        }
+                    };
+
+                    if !open {
+                        break;
+                    }
+                }
+            }
+        }
+
+        let _ = self.session.close(None).await;
+    }
+
+    //false once the session is closed
+    async fn handle_request(&mut self, text: &str) -> bool {
+        let request = match serde_json::from_str::<ClientRequest>(text) {
+            Ok(request) => request,
+            Err(e) => {
+                return self.send(&ServerResponse::Error { message: e.to_string() }).await;
+            }
+        };
+
+        match request {
+            ClientRequest::Subscribe(subscription) => {
+                if subscription.market() != self.market {
+                    return self.send(&ServerResponse::Error { message: "Unknown market".to_string() }).await;
+                }
+
+                //fetched before acknowledging so a disabled feed is never subscribed
+                let l3_snapshot = match subscription {
+                    Subscription::L3 { .. } => {
+                        match self.fetch_l3_snapshot().await {
+                            Some(l3_snapshot) => Some(l3_snapshot),
+                            None => {
+                                return self.send(&ServerResponse::Error { message: "L3 feed is not available, or the engine is busy".to_string() }).await;
+                            }
+                        }
+                    }
+                    _ => {
+                        None
+                    }
+                };
+
+                self.subscriptions.insert(subscription.clone());
+                if !self.send(&ServerResponse::Subscribed(subscription.clone())).await {
+                    return false;
+                }
+
+                match subscription {
+                    Subscription::Depth { .. } => {
+                        self.send_depth_snapshot().await
+                    }
+                    Subscription::L3 { .. } => {
+                        match l3_snapshot {
+                            Some(l3_snapshot) => self.send(&SessionEvent::L3Snapshot(l3_snapshot)).await,
+                            None => true
+                        }
+                    }
+                    //the indicative price only changes once a second, start with the current one
+                    Subscription::Auction { .. } => {
+                        let auction = self.snapshot.borrow().auction.clone();
+                        match auction {
+                            Some(auction) => self.send(&MarketEvent::Auction(auction)).await,
+                            None => true
+                        }
+                    }
+                    _ => {
+                        true
+                    }
+                }
+            }
+            ClientRequest::Unsubscribe(subscription) => {
+                self.subscriptions.remove(&subscription);
+                self.send(&ServerResponse::Unsubscribed(subscription)).await
+            }
+        }
+    }
+
+    async fn handle_event(&mut self, event: &MarketEvent) -> bool {
+        if !self.subscriptions.contains(&event.subscription()) {
+            return true;
+        }
+
+        self.send(event).await
+    }
+
+    //the snapshot is published before the update that produced it, so
+    //updates still queued for this session are at or below its seq
+    async fn send_depth_snapshot(&mut self) -> bool {
+        let depth = self.snapshot.borrow().depth(SNAPSHOT_DEPTH);
+        self.send(&SessionEvent::DepthSnapshot(depth)).await
+    }
+
+    //the engine answers between instructions, so the snapshot seq matches the l3 updates exactly.
+    //None when the feed is off or the reads lane is full, the session never waits for room in it
+    async fn fetch_l3_snapshot(&mut self) -> Option<L3Snapshot> {
+        let (reply_tx, reply_rx) = oneshot::channel::<L3Snapshot>();
+        let ix = EngineIx::L3Snapshot {
+            market: self.market.clone(),
+            reply: reply_tx
+        };
+
+        if self.engine_tx.try_send(ix).is_err() {
+            return None;
+        }
+
+        reply_rx.await.ok()
+    }
+
+    async fn resync_book(&mut self) -> bool {
+        let depth = self.subscriptions.iter().any(|subscription| matches!(subscription, Subscription::Depth { .. }));
+        if depth && !self.send_depth_snapshot().await {
+            return false;
+        }
+
+        let l3 = self.subscriptions.iter().any(|subscription| matches!(subscription, Subscription::L3 { .. }));
+        if l3 {
+            return match self.fetch_l3_snapshot().await {
+                Some(l3_snapshot) => self.send(&SessionEvent::L3Snapshot(l3_snapshot)).await,
+                None => true
+            };
+        }
+
+        true
+    }
+
+    async fn send<T: Serialize>(&mut self, msg: &T) -> bool {
+        let text = match serde_json::to_string(msg) {
+            Ok(text) => text,
+            Err(e) => {
+                eprintln!("Failed to serialize websocket message: {}", e);
+                return true;
+            }
+        };
+
+        self.session.text(text).await.is_ok()
+    }
+}
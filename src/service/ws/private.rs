@@ -0,0 +1,96 @@
+//private websocket for one authenticated user.
+//
+//connect to GET /ws/private with the same credentials as the REST api, either
+//`Authorization: Bearer <jwt>` or a signed API key request with the read scope.
+//there is nothing to subscribe to, every update for the user is pushed as {"channel": ..., "data": {...}}:
+//  order    {"kind": "accepted" | "partially_filled" | "filled" | "cancelled", "order": {...}}
+//  fill     one side of a trade, with the fee charged and whether the order was maker or taker
+//  balance  the whole UserBalance after it changed
+//  rejected {"order_id": ..., "reason": ...} for an order or cancel the engine did not execute
+//updates are pushed as the engine hands them to the persistence workers, so they can be ahead of REST queries.
+//a client that falls too far behind gets {"event": "error", ...} and should reload its state over REST
+use actix_ws::{AggregatedMessage, AggregatedMessageStream, Session};
+use serde::Serialize;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::service::{ServerResponse, UserFeedReceiver};
+
+//clients only send pings and close frames
+const MAX_FRAME_SIZE: usize = 1024;
+
+pub struct PrivateWsSession {
+    session: Session
+}
+
+impl PrivateWsSession {
+    pub fn default(session: Session) -> Self {
+        Self {
+            session: session
+        }
+    }
+
+    //events only come from the session's own user channel. runs until the client disconnects or the engine stops
+    pub async fn run(mut self, stream: actix_ws::MessageStream, mut events: UserFeedReceiver) {
+        let mut stream: AggregatedMessageStream = stream
+            .max_frame_size(MAX_FRAME_SIZE)
+            .aggregate_continuations()
+            .max_continuation_size(MAX_FRAME_SIZE);
+
+        loop {
+            tokio::select! {
+                msg = stream.recv() => {
+                    let Some(Ok(msg)) = msg else {
+                        break;
+                    };
+
+                    let open = match msg {
+                        AggregatedMessage::Ping(bytes) => {
+                            self.session.pong(&bytes).await.is_ok()
+                        }
+                        AggregatedMessage::Close(_) => {
+                            false
+                        }
+                        _ => {
+                            true
+                        }
+                    };
+
+                    if !open {
+                        break;
+                    }
+                }
+                event = events.recv() => {
+                    let open = match event {
+                        Ok(event) => {
+                            self.send(&event).await
+                        }
+                        Err(RecvError::Lagged(missed)) => {
+                            self.send(&ServerResponse::Error { message: format!("Missed {} updates", missed) }).await
+                        }
+                        Err(RecvError::Closed) => {
+                            false
+                        }
+                    };
+
+                    if !open {
+                        break;
+                    }
+                }
+            }
+        }
+
+        let _ = self.session.close(None).await;
+    }
+
+    async fn send<T: Serialize>(&mut self, msg: &T) -> bool {
+        let text = match serde_json::to_string(msg) {
+            Ok(text) => text,
+            Err(e) => {
+                eprintln!("Failed to serialize websocket message: {}", e);
+                return true;
+            }
+        };
+
+        self.session.text(text).await.is_ok()
+    }
+}